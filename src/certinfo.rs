@@ -0,0 +1,180 @@
+use std::net::TcpStream;
+use openssl::nid::Nid;
+use openssl::pkey::Id;
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl::x509::{X509NameRef, X509Ref, X509VerifyResult};
+use reqwest::Url;
+use serde_json::{json, Value};
+
+/// What the server showed during a TLS handshake.
+pub struct CertInfo {
+    pub protocol: String,
+    pub cipher: String,
+    /// Why the chain did not verify against the system roots and the host name, if it did not.
+    pub verify_error: Option<String>,
+    pub chain: Vec<Cert>,
+}
+
+pub struct Cert {
+    pub subject: String,
+    pub issuer: String,
+    pub sans: Vec<String>,
+    pub not_before: String,
+    pub not_after: String,
+    pub key: String,
+}
+
+/// Does a handshake of its own with the host of `url` and reads the chain. The chain is
+/// read even when it does not verify, the reason is kept in `verify_error`.
+pub async fn inspect(url: &Url) -> Result<CertInfo, String> {
+    let host = url.host_str().ok_or_else(|| format!("Missing host in {}", url))?.to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    tokio::task::spawn_blocking(move || handshake(&host, port))
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+fn handshake(host: &str, port: u16) -> Result<CertInfo, String> {
+    let mut connector = SslConnector::builder(SslMethod::tls()).map_err(|err| err.to_string())?;
+    // Verification still runs and is reported, it just does not end the handshake.
+    connector.set_verify(SslVerifyMode::NONE);
+    let config = connector.build().configure().map_err(|err| err.to_string())?;
+    let tcp = TcpStream::connect((host.trim_matches(['[', ']']), port))
+        .map_err(|err| format!("Could not connect to {}:{}: {}", host, port, err))?;
+    let stream = config
+        .connect(host.trim_matches(['[', ']']), tcp)
+        .map_err(|err| format!("TLS handshake with {} failed: {}", host, err))?;
+    let ssl = stream.ssl();
+
+    let verify_error = match ssl.verify_result() {
+        X509VerifyResult::OK => None,
+        result => Some(result.error_string().to_string()),
+    };
+    let chain = ssl
+        .peer_cert_chain()
+        .map(|chain| chain.iter().map(describe).collect())
+        .unwrap_or_default();
+    Ok(CertInfo {
+        protocol: ssl.version_str().to_string(),
+        cipher: ssl.current_cipher().map(|c| c.name().to_string()).unwrap_or_default(),
+        verify_error,
+        chain,
+    })
+}
+
+fn describe(cert: &X509Ref) -> Cert {
+    let sans = cert
+        .subject_alt_names()
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|name| {
+                    if let Some(dns) = name.dnsname() {
+                        Some(format!("DNS:{}", dns))
+                    } else if let Some(ip) = name.ipaddress() {
+                        ip_text(ip).map(|ip| format!("IP:{}", ip))
+                    } else {
+                        name.email().map(|email| format!("email:{}", email))
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Cert {
+        subject: name_text(cert.subject_name()),
+        issuer: name_text(cert.issuer_name()),
+        sans,
+        not_before: cert.not_before().to_string(),
+        not_after: cert.not_after().to_string(),
+        key: key_text(cert),
+    }
+}
+
+/// `CN=example.com, O=Example` from the entries of a name.
+fn name_text(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().as_utf8().map(|v| v.to_string()).unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn ip_text(bytes: &[u8]) -> Option<String> {
+    match bytes.len() {
+        4 => Some(std::net::Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).to_string()),
+        16 => Some(std::net::Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?).to_string()),
+        _ => None,
+    }
+}
+
+/// The key algorithm with its size, or the curve for EC keys.
+fn key_text(cert: &X509Ref) -> String {
+    let key = match cert.public_key() {
+        Ok(key) => key,
+        Err(_) => return "unknown".to_string(),
+    };
+    match key.id() {
+        Id::RSA => format!("RSA {} bits", key.bits()),
+        Id::EC => {
+            let curve = key
+                .ec_key()
+                .ok()
+                .and_then(|ec| ec.group().curve_name())
+                .and_then(|nid: Nid| nid.short_name().ok().map(|n| n.to_string()))
+                .unwrap_or_default();
+            format!("EC {} ({} bits)", curve, key.bits())
+        }
+        Id::ED25519 => "Ed25519".to_string(),
+        Id::ED448 => "Ed448".to_string(),
+        Id::DSA => format!("DSA {} bits", key.bits()),
+        _ => format!("{} bits", key.bits()),
+    }
+}
+
+impl CertInfo {
+    /// Prints the handshake and the chain, leaf first, in the style of the verbose output.
+    pub fn print(&self) {
+        let verified = match &self.verify_error {
+            None => "certificate verified".to_string(),
+            Some(err) => format!("certificate NOT verified: {}", err),
+        };
+        println!("* TLS {} {}, {}", self.protocol, self.cipher, verified);
+        for (i, cert) in self.chain.iter().enumerate() {
+            println!("* Certificate {}", i);
+            println!("*   subject: {}", cert.subject);
+            println!("*   issuer:  {}", cert.issuer);
+            if !cert.sans.is_empty() {
+                println!("*   SANs:    {}", cert.sans.join(", "));
+            }
+            println!("*   valid:   {} to {}", cert.not_before, cert.not_after);
+            println!("*   key:     {}", cert.key);
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let chain: Vec<Value> = self
+            .chain
+            .iter()
+            .map(|cert| {
+                json!({
+                    "subject": cert.subject,
+                    "issuer": cert.issuer,
+                    "sans": cert.sans,
+                    "not_before": cert.not_before,
+                    "not_after": cert.not_after,
+                    "key": cert.key,
+                })
+            })
+            .collect();
+        json!({
+            "protocol": self.protocol,
+            "cipher": self.cipher,
+            "verified": self.verify_error.is_none(),
+            "verify_error": self.verify_error,
+            "chain": chain,
+        })
+    }
+}
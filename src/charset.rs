@@ -0,0 +1,83 @@
+use clap::ArgMatches;
+use encoding_rs::{Encoding, UTF_8};
+use mime::Mime;
+use regex::Regex;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+
+/// How far into an HTML body a `<meta>` charset is looked for, as browsers do.
+const META_PREFIX: usize = 1024;
+
+/// The encoding --charset forces on every response.
+pub fn forced(matches: &ArgMatches) -> Option<&'static Encoding> {
+    matches.value_of("charset").map(|label| {
+        Encoding::for_label(label.trim().as_bytes()).unwrap_or_else(|| panic!("Unknown --charset {}", label))
+    })
+}
+
+/// Decodes the body to UTF-8. Unless `forced`, a byte order mark wins, then the charset of the
+/// Content-Type header, then a `<meta>` charset in HTML bodies, and UTF-8 otherwise.
+pub fn decode(headers: &HeaderMap, body: &[u8], forced: Option<&'static Encoding>) -> String {
+    if let Some(encoding) = forced {
+        return encoding.decode_with_bom_removal(body).0.into_owned();
+    }
+    let mime = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<Mime>().ok());
+    let declared = mime
+        .as_ref()
+        .and_then(|mime| mime.get_param("charset"))
+        .and_then(|charset| Encoding::for_label(charset.as_str().as_bytes()));
+    let is_html = mime.as_ref().is_none_or(|mime| mime.subtype() == mime::HTML);
+    let encoding = declared
+        .or_else(|| if is_html { sniff_meta(body) } else { None })
+        .unwrap_or(UTF_8);
+    // decode() lets a byte order mark override the encoding.
+    encoding.decode(body).0.into_owned()
+}
+
+/// `<meta charset="...">` or `<meta http-equiv="Content-Type" content="...; charset=...">`.
+fn sniff_meta(body: &[u8]) -> Option<&'static Encoding> {
+    let prefix = String::from_utf8_lossy(&body[..body.len().min(META_PREFIX)]);
+    let meta = Regex::new(r#"(?i)<meta\s[^>]*charset\s*=\s*["']?\s*([\w.:-]+)"#).unwrap();
+    let label = meta.captures(&prefix)?.get(1)?.as_str();
+    // A UTF-16 label in an ASCII compatible document is a mistake, browsers read it as UTF-8.
+    Encoding::for_label(label.as_bytes()).map(|encoding| encoding.output_encoding())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn content_type(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn honors_the_declared_charset() {
+        let headers = content_type("text/plain; charset=ISO-8859-1");
+        assert_eq!(decode(&headers, b"caf\xe9", None), "café");
+        let headers = content_type("text/plain; charset=Shift_JIS");
+        assert_eq!(decode(&headers, b"\x93\xfa\x96\x7b", None), "日本");
+    }
+
+    #[test]
+    fn sniffs_html_meta_and_bom() {
+        let html = b"<html><head><meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1252\"></head>\x80";
+        assert!(decode(&content_type("text/html"), html, None).ends_with('€'));
+        assert!(decode(&HeaderMap::new(), b"<meta charset='latin1'>\xe9", None).ends_with('é'));
+        // Only HTML bodies are sniffed.
+        assert!(decode(&content_type("text/plain"), b"<meta charset=latin1>\xe9", None).ends_with('\u{fffd}'));
+        let headers = content_type("text/plain; charset=ISO-8859-1");
+        assert_eq!(decode(&headers, b"\xef\xbb\xbfcaf\xc3\xa9", None), "café");
+    }
+
+    #[test]
+    fn forced_charset_wins() {
+        let headers = content_type("text/plain; charset=utf-8");
+        assert_eq!(decode(&headers, b"caf\xe9", Encoding::for_label(b"latin1")), "café");
+    }
+}
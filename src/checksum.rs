@@ -0,0 +1,51 @@
+use clap::ArgMatches;
+use md5::Md5;
+use sha2::digest::DynDigest;
+use sha2::{Digest, Sha256, Sha512};
+
+pub const CHECKSUM_ARGS: [&str; 3] = ["expected-md5", "expected-sha256", "expected-sha512"];
+
+/// Hashes the body while it is received and compares it with an --expected-* digest.
+pub struct Checksum {
+    name: &'static str,
+    expected: String,
+    hasher: Box<dyn DynDigest + Send>,
+}
+
+impl Checksum {
+    pub fn from_matches(matches: &ArgMatches) -> Vec<Checksum> {
+        CHECKSUM_ARGS
+            .iter()
+            .filter_map(|arg| matches.value_of(arg).map(|expected| (*arg, expected)))
+            .map(|(arg, expected)| {
+                let (name, hasher): (&str, Box<dyn DynDigest + Send>) = match arg {
+                    "expected-md5" => ("md5", Box::new(Md5::new())),
+                    "expected-sha256" => ("sha256", Box::new(Sha256::new())),
+                    _ => ("sha512", Box::new(Sha512::new())),
+                };
+                Checksum {
+                    name,
+                    expected: expected.trim().to_lowercase(),
+                    hasher,
+                }
+            })
+            .collect()
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+    }
+
+    /// Returns the mismatch message, or `None` when the digest is the expected one.
+    pub fn verify(self) -> Option<String> {
+        let actual: String = self.hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+        if actual == self.expected {
+            None
+        } else {
+            Some(format!(
+                "{} mismatch\n- expected: {}\n+ actual:   {}",
+                self.name, self.expected, actual
+            ))
+        }
+    }
+}
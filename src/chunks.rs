@@ -0,0 +1,102 @@
+use std::time::Duration;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT_RANGES, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Client, Request, Response, StatusCode};
+
+/// How often a chunk is requested before the whole download is given up.
+pub const CHUNK_ATTEMPTS: u32 = 3;
+
+/// The length of the body when `response` can be fetched in `chunks` ranges: the server
+/// accepts byte ranges, sent a length and the body is at least one byte per chunk.
+pub fn probe(response: &Response, chunks: usize) -> Option<u64> {
+    let accepts = response
+        .headers()
+        .get(ACCEPT_RANGES)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("bytes"));
+    let len = response.content_length()?;
+    (accepts && chunks > 1 && response.status() == StatusCode::OK && len >= chunks as u64).then_some(len)
+}
+
+/// The strong ETag or else the Last-Modified date, sent as If-Range so a server whose
+/// file changed meanwhile answers 200 instead of mixing two versions.
+pub fn validator(headers: &HeaderMap) -> Option<HeaderValue> {
+    headers
+        .get(ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+        .or_else(|| headers.get(LAST_MODIFIED))
+        .cloned()
+}
+
+/// Fetches the `len` bytes `template` points at in `chunks` concurrent range requests and
+/// joins them in order. Each chunk is retried on its own.
+pub async fn download(
+    client: &Client,
+    template: &Request,
+    validator: Option<HeaderValue>,
+    len: u64,
+    chunks: usize,
+    verbose: bool,
+) -> Result<Vec<u8>, String> {
+    let size = len.div_ceil(chunks as u64);
+    let mut tasks = Vec::new();
+    let mut start = 0;
+    while start < len {
+        let end = (start + size).min(len) - 1;
+        let mut req = template.try_clone().ok_or("The request cannot be repeated in ranges")?;
+        let range = HeaderValue::from_str(&format!("bytes={}-{}", start, end)).unwrap();
+        req.headers_mut().insert(RANGE, range);
+        if let Some(validator) = &validator {
+            req.headers_mut().insert(IF_RANGE, validator.clone());
+        }
+        let client = client.clone();
+        tasks.push(tokio::spawn(async move { fetch(&client, req, start, end, verbose).await }));
+        start = end + 1;
+    }
+
+    let mut body = Vec::with_capacity(len as usize);
+    for task in tasks {
+        body.extend_from_slice(&task.await.expect("Chunk download panicked")?);
+    }
+    Ok(body)
+}
+
+/// Requests bytes `start..=end`, retrying with a growing pause.
+async fn fetch(client: &Client, req: Request, start: u64, end: u64, verbose: bool) -> Result<Vec<u8>, String> {
+    let mut error = String::new();
+    for attempt in 1..=CHUNK_ATTEMPTS {
+        match fetch_once(client, req.try_clone().unwrap(), start, end).await {
+            Ok(bytes) => return Ok(bytes),
+            Err(err) => error = err,
+        }
+        if verbose {
+            println!("* Bytes {}-{} failed (attempt {} of {}): {}", start, end, attempt, CHUNK_ATTEMPTS, error);
+        }
+        if attempt < CHUNK_ATTEMPTS {
+            tokio::time::sleep(Duration::from_millis(250 * attempt as u64)).await;
+        }
+    }
+    Err(format!("Bytes {}-{} failed after {} attempts: {}", start, end, CHUNK_ATTEMPTS, error))
+}
+
+async fn fetch_once(client: &Client, req: Request, start: u64, end: u64) -> Result<Vec<u8>, String> {
+    let response = client.execute(req).await.map_err(|err| err.to_string())?;
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => {}
+        StatusCode::OK => return Err("the file changed on the server or ranges are not supported".to_string()),
+        status => return Err(format!("the server answered {}", status)),
+    }
+    let range = response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    if !range.starts_with(&format!("bytes {}-{}/", start, end)) {
+        return Err(format!("unexpected Content-Range {:?}", range));
+    }
+    let bytes = response.bytes().await.map_err(|err| err.to_string())?;
+    if bytes.len() as u64 != end - start + 1 {
+        return Err(format!("got {} of {} bytes", bytes.len(), end - start + 1));
+    }
+    Ok(bytes.to_vec())
+}
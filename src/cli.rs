@@ -0,0 +1,603 @@
+use clap::{Arg, Command};
+use crate::{compress, highlight};
+
+pub fn build() -> Command<'static> {
+    Command::new(
+        env!("CARGO_PKG_NAME")
+    ).version(
+        env!("CARGO_PKG_VERSION")
+    ).about("Cli tool that makes request to the endpoints and processes the responses")
+        .author("BufferOverflow")
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("completions")
+                .about("Prints a shell completion script")
+                .arg(
+                    Arg::new("shell")
+                        .index(1)
+                        .required(true)
+                        .possible_values(["bash", "zsh", "fish", "powershell", "elvish"])
+                )
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Runs the requests of a request file in order, with captures and assertions")
+                .arg(
+                    Arg::new("file")
+                        .index(1)
+                        .required(true)
+                        .value_name("FILE")
+                )
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .help("Prints every request and response head")
+                )
+                .arg(
+                    Arg::new("parallel")
+                        .long("parallel")
+                        .help("Runs the steps at the same time, each one waiting only for the steps named in its @after lines")
+                )
+                .arg(
+                    Arg::new("redact-header")
+                        .long("redact-header")
+                        .value_name("NAME")
+                        .multiple_occurrences(true)
+                        .help("Masks the value of this header in verbose output and report attachments, in addition to credentials and cookies")
+                )
+                .arg(
+                    Arg::new("show-secrets")
+                        .long("show-secrets")
+                        .help("Prints sensitive header values in verbose output and report attachments instead of <redacted>")
+                )
+                .arg(
+                    Arg::new("var")
+                        .long("var")
+                        .value_name("NAME=VALUE")
+                        .multiple_occurrences(true)
+                        .help("Sets a {{NAME}} placeholder, overriding the var file and environment")
+                )
+                .arg(
+                    Arg::new("var-file")
+                        .long("var-file")
+                        .value_name("FILE")
+                        .help("Reads NAME=VALUE lines for {{NAME}} placeholders")
+                )
+                .arg(
+                    Arg::new("report")
+                        .long("report")
+                        .value_names(&["FORMAT", "FILE"])
+                        .number_of_values(2)
+                        .help("Writes a test report of the steps (junit, tap, gha or html) to FILE, - for stdout")
+                )
+                .arg(
+                    Arg::new("attach-limit")
+                        .long("attach-limit")
+                        .value_name("SIZE")
+                        .help("Attaches at most SIZE bytes of a failed response body to the --report, 64k by default, 0 to turn it off")
+                )
+                .args(redirect_args())
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Sets the output level to verbose")
+        )
+        .arg(
+            Arg::new("method")
+                .short('X')
+                .long("method")
+                .takes_value(true)
+                .possible_values(["POST", "GET", "PUT", "PATCH", "HEAD", "DELETE"])
+                .ignore_case(true)
+                .help("Sets the http method for the request")
+        )
+        .arg(
+            Arg::new("header")
+                .short('H')
+                .multiple_occurrences(true)
+                .takes_value(true)
+                .help("Sets header content for the request")
+        )
+        .arg(
+            Arg::new("raw-request")
+                .long("raw-request")
+                .value_name("FILE")
+                .conflicts_with_all(&["trailer", "data", "form", "header"])
+                .help("Sends the HTTP/1.1 request in FILE byte for byte to the host of the url")
+        )
+        .arg(
+            Arg::new("trailer")
+                .long("trailer")
+                .value_name("NAME: VALUE")
+                .multiple_occurrences(true)
+                .help("Sends the body chunked over HTTP/1.1 with this trailer after it, -v shows the trailers of the response")
+        )
+        .arg(
+            Arg::new("form")
+                .short('F')
+                .takes_value(true)
+                .multiple_values(true)
+                .help("Set the form values in a field=value pair")
+        )
+        .arg(
+            Arg::new("data")
+                .short('d')
+                .multiple_values(true)
+                .takes_value(true)
+                .help("Sets the data values and combine from a field=value pair")
+        )
+        .arg(
+            Arg::new("uri")
+                .index(1)
+                .required_unless_present("url-file")
+                .multiple_values(true)
+                .help("The urls to request, {a,b} and [1-10] globs send one request each")
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Prints the request that would be sent, with globs and placeholders filled, without sending it")
+        )
+        .arg(
+            Arg::new("url-file")
+                .long("url-file")
+                .visible_alias("urls")
+                .value_name("FILE")
+                .help("Reads more urls from FILE, - for stdin, one per line and optionally followed by the path to save it in and priority=N; higher priorities are fetched first")
+        )
+        .arg(
+            Arg::new("out")
+                .value_name("PATH")
+                .short('o')
+                .long("out-path")
+                .help("Saves the response in the file, #1, #2 ... are replaced with what each url glob matched")
+        )
+        .arg(
+            Arg::new("remote-name-all")
+                .long("remote-name-all")
+                .conflicts_with("out")
+                .help("Saves each response in a file named like the last part of its url")
+        )
+        .arg(
+            Arg::new("no-clobber")
+                .long("no-clobber")
+                .help("Saves as PATH.1, PATH.2 ... instead of overwriting a file that exists")
+        )
+        .arg(
+            Arg::new("remote-time")
+                .short('R')
+                .long("remote-time")
+                .help("Gives the saved file the Last-Modified time the server sent")
+        )
+        .arg(
+            Arg::new("with-frontmatter")
+                .long("with-frontmatter")
+                .requires("out")
+                .help("Prefixes the saved file with a commented YAML block describing the response")
+        )
+        .arg(
+            Arg::new("append")
+                .long("append")
+                .requires("out")
+                .help("Appends the response to the output file instead of overwriting it")
+        )
+        .arg(
+            Arg::new("record-separator")
+                .long("record-separator")
+                .value_name("SEP")
+                .allow_hyphen_values(true)
+                .requires("append")
+                .help("Written between appended entries, supports \\n, \\t and \\r escapes")
+        )
+        .arg(
+            Arg::new("redact-header")
+                .long("redact-header")
+                .value_name("NAME")
+                .multiple_occurrences(true)
+                .help("Masks the value of this header in verbose output, in addition to credentials and cookies")
+        )
+        .arg(
+            Arg::new("show-secrets")
+                .long("show-secrets")
+                .help("Prints sensitive header values in verbose output instead of <redacted>")
+        )
+        .arg(
+            Arg::new("trace")
+                .long("trace")
+                .value_name("FILE")
+                .conflicts_with("trace-ascii")
+                .help("Writes a hex and ASCII dump of all sent and received data, - for stderr")
+        )
+        .arg(
+            Arg::new("trace-ascii")
+                .long("trace-ascii")
+                .value_name("FILE")
+                .help("Like --trace but without the hex columns")
+        )
+        .arg(
+            Arg::new("trace-time")
+                .long("trace-time")
+                .help("Prefixes every trace entry with a UTC timestamp")
+        )
+        .arg(
+            Arg::new("grep")
+                .long("grep")
+                .value_name("PATTERN")
+                .help("Only prints the body lines matching the regular expression")
+        )
+        .arg(
+            Arg::new("context")
+                .short('C')
+                .long("context")
+                .value_name("NUM")
+                .requires("grep")
+                .help("Prints NUM lines of context around every --grep match")
+        )
+        .arg(
+            Arg::new("head-lines")
+                .long("head-lines")
+                .value_name("NUM")
+                .conflicts_with("tail-lines")
+                .help("Only prints the first NUM lines of the body")
+        )
+        .arg(
+            Arg::new("tail-lines")
+                .long("tail-lines")
+                .value_name("NUM")
+                .help("Only prints the last NUM lines of the body")
+        )
+        .arg(
+            Arg::new("output-format")
+                .long("output-format")
+                .value_name("FORMAT")
+                .possible_values(["text", "json"])
+                .help("json prints the request, response, timing and body as one JSON document")
+        )
+        .arg(
+            Arg::new("hexdump")
+                .long("hexdump")
+                .help("Prints the whole body as hex and ASCII, binary bodies on a terminal get a short preview anyway")
+        )
+        .arg(
+            Arg::new("raw")
+                .long("raw")
+                .help("Prints XML bodies as received instead of re-indenting them on a terminal")
+        )
+        .arg(
+            Arg::new("charset")
+                .long("charset")
+                .value_name("LABEL")
+                .help("Decodes text bodies as LABEL, like Shift_JIS, instead of the charset the response declares")
+        )
+        .arg(
+            Arg::new("highlight")
+                .long("highlight")
+                .value_name("LANG")
+                .possible_values(highlight::LANGUAGES)
+                .help("Highlights the body as LANG instead of guessing from the content type, none to turn it off")
+        )
+        .arg(
+            Arg::new("log-json")
+                .long("log-json")
+                .value_name("FILE")
+                .help("Appends a JSON record with status, timings and byte counts of the request to FILE")
+        )
+        .arg(
+            Arg::new("otel")
+                .long("otel")
+                .help("Sends a W3C traceparent header and exports a client span to OTEL_EXPORTER_OTLP_ENDPOINT")
+        )
+        .arg(
+            Arg::new("sni-name")
+                .long("sni-name")
+                .value_name("NAME")
+                .help("Sends NAME as TLS server name and default Host but still connects to the address of the url")
+        )
+        .arg(
+            Arg::new("cert-info")
+                .long("cert-info")
+                .help("Prints the TLS version, cipher and certificate chain of https servers, in the document with --output-format json")
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("FILE")
+                .help("Reads [host \"name\"] profiles from FILE instead of ~/.config/rust-curl/config")
+        )
+        .arg(
+            Arg::new("cert")
+                .short('E')
+                .long("cert")
+                .value_name("FILE")
+                .help("Client certificate for mutual TLS, a PEM file or a PKCS#12 bundle")
+        )
+        .arg(
+            Arg::new("cert-type")
+                .long("cert-type")
+                .value_name("TYPE")
+                .possible_values(["PEM", "P12"])
+                .ignore_case(true)
+                .requires("cert")
+                .help("Format of the client certificate, defaults to PEM")
+        )
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .value_name("FILE")
+                .requires("cert")
+                .help("PKCS#8 private key for a PEM certificate if it is not in the certificate file")
+        )
+        .arg(
+            Arg::new("pass")
+                .long("pass")
+                .value_name("PHRASE")
+                .requires("cert")
+                .help("Passphrase of the PKCS#12 bundle")
+        )
+        .arg(
+            Arg::new("compress-request")
+                .long("compress-request")
+                .value_name("ENCODING")
+                .possible_values(compress::ENCODINGS)
+                .help("Compresses the request body in memory and sets Content-Encoding")
+        )
+        .arg(
+            Arg::new("idempotency-key")
+                .long("idempotency-key")
+                .value_name("KEY")
+                .help("Sends an Idempotency-Key header, auto for a random UUID that all --mirror attempts reuse")
+        )
+        .arg(
+            Arg::new("hmac-sign")
+                .long("hmac-sign")
+                .value_name("SPEC")
+                .help("Signs the request, SPEC is key=SECRET[,algo=sha256|sha512][,header=X-Signature]\
+                    [,encoding=hex|base64][,prefix=TEXT][,payload=TEMPLATE]. The payload template knows \
+                    {{method}}, {{path}}, {{query}}, {{host}}, {{date}}, {{body}} and {{body_sha256}}")
+        )
+        .arg(
+            Arg::new("upload-file")
+                .short('T')
+                .long("upload-file")
+                .value_name("FILE")
+                .help("Uploads FILE to the sftp:// or scp:// url instead of downloading it")
+        )
+        .arg(
+            Arg::new("ssh-key")
+                .long("ssh-key")
+                .value_name("FILE")
+                .help("Private key for sftp:// and scp://, otherwise the agent and ~/.ssh/id_* keys are tried")
+        )
+        .arg(
+            Arg::new("ssh-key-pass")
+                .long("ssh-key-pass")
+                .value_name("PHRASE")
+                .help("Passphrase of the ssh private key")
+        )
+        .arg(
+            Arg::new("ssh-insecure")
+                .long("ssh-insecure")
+                .help("Skips the ~/.ssh/known_hosts check of sftp:// and scp:// hosts")
+        )
+        .arg(
+            Arg::new("no-keepalive")
+                .long("no-keepalive")
+                .help("Closes each connection after its request instead of keeping it for reuse")
+        )
+        .arg(
+            Arg::new("keepalive-time")
+                .long("keepalive-time")
+                .value_name("SECONDS")
+                .help("Sends TCP keepalive probes on idle connections every SECONDS")
+        )
+        .arg(
+            Arg::new("tcp-nodelay")
+                .long("tcp-nodelay")
+                .overrides_with("no-tcp-nodelay")
+                .help("Sets TCP_NODELAY so small packets are sent at once (the default)")
+        )
+        .arg(
+            Arg::new("no-tcp-nodelay")
+                .long("no-tcp-nodelay")
+                .overrides_with("tcp-nodelay")
+                .help("Clears TCP_NODELAY so small packets are batched")
+        )
+        .arg(
+            Arg::new("dns-servers")
+                .long("dns-servers")
+                .value_name("IP,IP")
+                .help("Resolves host names with these DNS servers instead of the system resolver")
+        )
+        .arg(
+            Arg::new("dns-cache-timeout")
+                .long("dns-cache-timeout")
+                .value_name("SECONDS")
+                .help("Reuses a DNS answer for SECONDS within this run, 0 turns the cache off (default 60)")
+        )
+        .arg(
+            Arg::new("happy-eyeballs-timeout-ms")
+                .long("happy-eyeballs-timeout-ms")
+                .value_name("MS")
+                .help("Races the IPv6 and IPv4 addresses of the host, starting one every MS milliseconds, and uses the first that connects")
+        )
+        .arg(
+            Arg::new("interface")
+                .long("interface")
+                .value_name("NAME|IP")
+                .help("Sends the request from this network interface or source address")
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .value_names(&["FORMAT", "FILE"])
+                .number_of_values(2)
+                .help("Writes a test report of the request (junit, tap, gha or html) to FILE, - for stdout")
+        )
+        .arg(
+            Arg::new("attach-limit")
+                .long("attach-limit")
+                .value_name("SIZE")
+                .help("Attaches at most SIZE bytes of a failed response body to the --report, 64k by default, 0 to turn it off")
+        )
+        .arg(
+            Arg::new("expected-md5")
+                .long("expected-md5")
+                .value_name("HEX")
+                .help("Fails without saving or printing the body unless its MD5 digest is HEX")
+        )
+        .arg(
+            Arg::new("expected-sha256")
+                .long("expected-sha256")
+                .value_name("HEX")
+                .help("Fails without saving or printing the body unless its SHA-256 digest is HEX")
+        )
+        .arg(
+            Arg::new("expected-sha512")
+                .long("expected-sha512")
+                .value_name("HEX")
+                .help("Fails without saving or printing the body unless its SHA-512 digest is HEX")
+        )
+        .arg(
+            Arg::new("max-filesize")
+                .long("max-filesize")
+                .value_name("BYTES")
+                .help("Aborts with exit code 63 when the body is larger than BYTES (k, M and G suffixes allowed)")
+        )
+        .arg(
+            Arg::new("max-response-time")
+                .long("max-response-time")
+                .value_name("MS")
+                .help("Exits with 92 when the whole transfer takes longer than MS milliseconds")
+        )
+        .arg(
+            Arg::new("max-ttfb")
+                .long("max-ttfb")
+                .value_name("MS")
+                .help("Exits with 92 when the first response byte takes longer than MS milliseconds")
+        )
+        .arg(
+            Arg::new("expect-status")
+                .long("expect-status")
+                .value_name("CODES")
+                .help("Fails with exit code 90 unless the status is one of CODES, e.g. 200,204 or 2xx")
+        )
+        .arg(
+            Arg::new("expect-header")
+                .long("expect-header")
+                .value_name("NAME: VALUE")
+                .multiple_occurrences(true)
+                .help("Fails unless the response header contains VALUE")
+        )
+        .arg(
+            Arg::new("expect-body-contains")
+                .long("expect-body-contains")
+                .value_name("TEXT")
+                .multiple_occurrences(true)
+                .help("Fails unless the body contains TEXT")
+        )
+        .arg(
+            Arg::new("expect-json")
+                .long("expect-json")
+                .value_name("PATH=VALUE")
+                .multiple_occurrences(true)
+                .help("Fails unless the JSONPath selects VALUE, e.g. '$.items[0].id=1'")
+        )
+        .args(redirect_args())
+        .arg(
+            Arg::new("continue-at")
+                .long("continue-at")
+                .value_name("OFFSET")
+                .conflicts_with_all(&["append", "with-frontmatter", "parallel-chunks", "expected-md5", "expected-sha256", "expected-sha512"])
+                .help("Resumes a download at byte OFFSET, - continues where the -o file ends")
+        )
+        .arg(
+            Arg::new("parallel-chunks")
+                .long("parallel-chunks")
+                .value_name("N")
+                .help("Downloads a GET response in N concurrent range requests when the server accepts ranges")
+        )
+        .arg(
+            Arg::new("mirror")
+                .long("mirror")
+                .value_name("URL")
+                .multiple_occurrences(true)
+                .help("Tries URL when the previous url fails to connect, answers with 5xx or has the wrong checksum")
+        )
+        .arg(
+            Arg::new("mirror-file")
+                .long("mirror-file")
+                .value_name("FILE")
+                .help("Reads more --mirror urls from FILE, one per line")
+        )
+        .arg(
+            Arg::new("batch-progress")
+                .long("batch-progress")
+                .help("With several urls or rows, prints the files done, bytes received, speed and ETA of the whole batch after each transfer, as a JSON line with --output-format json")
+        )
+        .arg(
+            Arg::new("min-success-rate")
+                .long("min-success-rate")
+                .value_name("PERCENT")
+                .help("With several urls or rows, exits with 0 when at least PERCENT of the transfers got a status below 400 without an error, and prints their counts by status class")
+        )
+        .arg(
+            Arg::new("iterate")
+                .long("iterate")
+                .value_name("FILE")
+                .help("Sends the request once per CSV or NDJSON row, filling {{column}} placeholders")
+        )
+        .arg(
+            Arg::new("var")
+                .long("var")
+                .value_name("NAME=VALUE")
+                .multiple_occurrences(true)
+                .help("Sets a {{NAME}} placeholder in the url, headers and body, overriding the var file and environment")
+        )
+        .arg(
+            Arg::new("var-file")
+                .long("var-file")
+                .value_name("FILE")
+                .help("Reads NAME=VALUE lines for {{NAME}} placeholders, environment variables are available too")
+        )
+        .arg(
+            Arg::new("fan-out")
+                .long("fan-out")
+                .value_name("JSONPATH")
+                .requires("then")
+                .help("Sends the --then request once per element the path selects in the JSON response")
+        )
+        .arg(
+            Arg::new("then")
+                .long("then")
+                .value_name("REQUEST")
+                .requires("fan-out")
+                .help("Follow-up request like 'GET /items/{{id}}', relative to the first url")
+        )
+        .arg(
+            Arg::new("fan-out-concurrency")
+                .long("fan-out-concurrency")
+                .value_name("N")
+                .help("Maximum number of follow-up requests in flight, 4 by default")
+        )
+}
+
+/// Flags of redirect::Policy, shared by the request and the `run` subcommand.
+fn redirect_args() -> [Arg<'static>; 4] {
+    [
+        Arg::new("post301")
+            .long("post301")
+            .help("Keeps the method and body of a POST on a 301 redirect instead of switching to GET"),
+        Arg::new("post302")
+            .long("post302")
+            .help("Keeps the method and body of a POST on a 302 redirect instead of switching to GET"),
+        Arg::new("post303")
+            .long("post303")
+            .help("Keeps the method and body of a POST on a 303 redirect instead of switching to GET"),
+        Arg::new("location-trusted")
+            .long("location-trusted")
+            .help("Sends Authorization and Cookie headers on to redirects to other hosts"),
+    ]
+}
@@ -0,0 +1,20 @@
+use std::io::Write;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+pub const ENCODINGS: [&str; 2] = ["gzip", "zstd"];
+
+/// Compresses a request body for the given `Content-Encoding`. The body is already in memory,
+/// and the compressed copy stays there too so the request can still be cloned, traced, reported
+/// and replayed on redirects.
+pub fn compress(encoding: &str, data: &[u8]) -> Vec<u8> {
+    match encoding {
+        "gzip" => {
+            let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 2), Compression::default());
+            encoder.write_all(data).expect("Could not gzip the body");
+            encoder.finish().expect("Could not gzip the body")
+        }
+        "zstd" => zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL).expect("Could not zstd the body"),
+        _ => panic!("Invalid --compress-request encoding {}", encoding),
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use clap::ArgMatches;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// Seconds an answer is reused when --dns-cache-timeout is not given, the same as curl.
+pub const DEFAULT_CACHE_TIMEOUT: u64 = 60;
+
+/// Resolves host names through the system or the --dns-servers and remembers the
+/// answers for --dns-cache-timeout seconds. Cloning shares the cache.
+#[derive(Clone)]
+pub struct Resolver {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// `None` asks the system resolver.
+    servers: Option<TokioAsyncResolver>,
+    label: String,
+    cache: Mutex<HashMap<String, (Instant, Vec<IpAddr>)>>,
+    timeout: Duration,
+    verbose: bool,
+}
+
+/// The resolver all clients of this run share, so a name is looked up once for all urls.
+pub fn shared(matches: &ArgMatches) -> Resolver {
+    static SHARED: OnceLock<Resolver> = OnceLock::new();
+    SHARED.get_or_init(|| Resolver::from_matches(matches)).clone()
+}
+
+impl Resolver {
+    fn from_matches(matches: &ArgMatches) -> Resolver {
+        let servers: Option<Vec<IpAddr>> = matches.value_of("dns-servers").map(|list| {
+            list.split(',')
+                .map(|ip| ip.trim().parse().unwrap_or_else(|_| panic!("Invalid --dns-servers address {}", ip)))
+                .collect()
+        });
+        let timeout = matches
+            .value_of("dns-cache-timeout")
+            .map(|t| t.parse().expect("--dns-cache-timeout must be a number of seconds"))
+            .unwrap_or(DEFAULT_CACHE_TIMEOUT);
+        let label = match &servers {
+            Some(ips) => ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(", "),
+            None => "the system resolver".to_string(),
+        };
+        let servers = servers.map(|ips| {
+            let config = ResolverConfig::from_parts(None, Vec::new(), NameServerConfigGroup::from_ips_clear(&ips, 53, true));
+            TokioAsyncResolver::tokio(config, ResolverOpts::default())
+        });
+        Resolver {
+            inner: Arc::new(Inner {
+                servers,
+                label,
+                cache: Mutex::new(HashMap::new()),
+                timeout: Duration::from_secs(timeout),
+                verbose: matches.is_present("verbose"),
+            }),
+        }
+    }
+
+    /// The addresses of `host`, from the cache while the last answer is fresh enough.
+    pub async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>, String> {
+        if let Ok(ip) = host.trim_matches(['[', ']']).parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+        let inner = &self.inner;
+        let cached = inner.cache.lock().unwrap().get(host).cloned();
+        if let Some((_, addresses)) = cached.filter(|(at, _)| at.elapsed() < inner.timeout) {
+            if inner.verbose {
+                println!("* DNS cache hit for {}", host);
+            }
+            return Ok(addresses);
+        }
+
+        let addresses: Vec<IpAddr> = match &inner.servers {
+            Some(servers) => servers
+                .lookup_ip(host)
+                .await
+                .map_err(|err| format!("Could not resolve {}: {}", host, err))?
+                .iter()
+                .collect(),
+            None => tokio::net::lookup_host((host, 0))
+                .await
+                .map_err(|err| format!("Could not resolve {}: {}", host, err))?
+                .map(|address| address.ip())
+                .collect(),
+        };
+        if addresses.is_empty() {
+            return Err(format!("Could not resolve {}: no addresses", host));
+        }
+        if inner.verbose {
+            let list: Vec<String> = addresses.iter().map(|ip| ip.to_string()).collect();
+            println!("* DNS cache miss, resolved {} to {} with {}", host, list.join(", "), inner.label);
+        }
+        if !inner.timeout.is_zero() {
+            inner.cache.lock().unwrap().insert(host.to_string(), (Instant::now(), addresses.clone()));
+        }
+        Ok(addresses)
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addresses = resolver.lookup(name.as_str()).await?;
+            Ok(Box::new(addresses.into_iter().map(|ip| SocketAddr::new(ip, 0))) as Addrs)
+        })
+    }
+}
@@ -0,0 +1,121 @@
+use httpdate::fmt_http_date;
+use reqwest::header::HeaderMap;
+use reqwest::Request;
+use serde_json::{json, Map, Value};
+use crate::txlog::Transaction;
+use crate::Redactor;
+
+/// What --output-format json needs to remember about the request after it was sent.
+pub struct RequestInfo {
+    method: String,
+    url: String,
+    headers: Value,
+    body: Option<Value>,
+}
+
+impl RequestInfo {
+    pub fn new(req: &Request, redactor: &Redactor) -> RequestInfo {
+        RequestInfo {
+            method: req.method().to_string(),
+            url: req.url().to_string(),
+            headers: headers_json(req.headers(), redactor),
+            body: req.body().and_then(|b| b.as_bytes()).map(body_bytes),
+        }
+    }
+}
+
+/// The response body as it appears in the document.
+pub enum Body {
+    /// Decoded text.
+    Text(String),
+    /// Not text, inlined as base64.
+    Binary(Vec<u8>),
+    /// Written to a file with -o.
+    File(String),
+}
+
+pub struct ResponseInfo<'a> {
+    pub status: u16,
+    pub version: String,
+    pub headers: &'a HeaderMap,
+    pub body: Body,
+}
+
+/// Renders the whole transaction as a single JSON document. `tls` is what --cert-info found.
+pub fn render(
+    request: &RequestInfo,
+    response: Option<ResponseInfo>,
+    transaction: &Transaction,
+    tls: Option<&Value>,
+    redactor: &Redactor,
+) -> String {
+    let response = response.map(|response| {
+        let body = match response.body {
+            Body::Text(text) => json!({ "text": text }),
+            Body::Binary(bytes) => json!({ "base64": base64::encode(bytes) }),
+            Body::File(path) => json!({ "file": path }),
+        };
+        json!({
+            "status": response.status,
+            "version": response.version,
+            "headers": headers_json(response.headers, redactor),
+            "body": body,
+        })
+    });
+    let mut document = json!({
+        "request": {
+            "method": request.method,
+            "url": request.url,
+            "headers": request.headers,
+            "body": request.body,
+        },
+        "response": response,
+        "timing": {
+            "started_at": fmt_http_date(transaction.started_at()),
+            "ttfb_ms": transaction.ttfb().map(|d| d.as_secs_f64() * 1000.0),
+            "total_ms": transaction.elapsed().as_secs_f64() * 1000.0,
+        },
+        "bytes_sent": transaction.bytes_sent(),
+        "bytes_received": transaction.bytes_received(),
+        "error": transaction.error(),
+    });
+    if let Some(tls) = tls {
+        document["tls"] = tls.clone();
+    }
+    serde_json::to_string_pretty(&document).unwrap()
+}
+
+/// Header names map to their value, or to a list when the header repeats.
+fn headers_json(headers: &HeaderMap, redactor: &Redactor) -> Value {
+    let mut map = Map::new();
+    for name in headers.keys() {
+        let values: Vec<Value> = headers
+            .get_all(name)
+            .iter()
+            .map(|v| Value::String(redactor.display(name.as_str(), &String::from_utf8_lossy(v.as_bytes())).to_string()))
+            .collect();
+        let value = match values.len() {
+            1 => values.into_iter().next().unwrap(),
+            _ => Value::Array(values),
+        };
+        map.insert(name.to_string(), value);
+    }
+    Value::Object(map)
+}
+
+fn body_bytes(bytes: &[u8]) -> Value {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "base64": base64::encode(bytes) }),
+    }
+}
+
+/// Bodies count as text when the content type says so or they are valid UTF-8 without NUL bytes.
+pub fn is_text(headers: &HeaderMap, bytes: &[u8]) -> bool {
+    let declared = crate::highlight::detect(headers).is_some()
+        || headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/") || v.contains("charset="));
+    declared || (std::str::from_utf8(bytes).is_ok() && !bytes.contains(&0))
+}
@@ -0,0 +1,115 @@
+use clap::ArgMatches;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde_json::Value;
+use crate::jsonpath;
+
+pub const EXPECT_ARGS: [&str; 4] = ["expect-status", "expect-header", "expect-body-contains", "expect-json"];
+
+pub fn is_requested(matches: &ArgMatches) -> bool {
+    EXPECT_ARGS.iter().any(|arg| matches.is_present(arg))
+}
+
+/// Checks the response against the --expect-* flags and returns one message per failed expectation.
+pub fn check(matches: &ArgMatches, status: StatusCode, headers: &HeaderMap, body: &str) -> Vec<String> {
+    let mut failures = Vec::new();
+
+    if let Some(expected) = matches.value_of("expect-status") {
+        if !status_matches(expected, status) {
+            failures.push(diff("status", expected, &status.as_u16().to_string()));
+        }
+    }
+
+    for expected in matches.values_of("expect-header").unwrap_or_default() {
+        let (name, value) = expected
+            .split_once(':')
+            .unwrap_or_else(|| panic!("Unexpected --expect-header format {}", expected));
+        let name = name.trim();
+        let value = value.trim();
+        let actual: Vec<&str> = headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        let found = actual
+            .iter()
+            .any(|v| v.to_lowercase().contains(&value.to_lowercase()));
+        if !found {
+            let actual = if actual.is_empty() {
+                "<missing>".to_string()
+            } else {
+                actual.join(", ")
+            };
+            failures.push(diff(&format!("header {}", name), value, &actual));
+        }
+    }
+
+    for expected in matches.values_of("expect-body-contains").unwrap_or_default() {
+        if !body.contains(expected) {
+            failures.push(diff("body contains", expected, &preview(body)));
+        }
+    }
+
+    let json_expectations: Vec<&str> = matches.values_of("expect-json").unwrap_or_default().collect();
+    if !json_expectations.is_empty() {
+        match serde_json::from_str::<Value>(body) {
+            Ok(json) => {
+                for expected in json_expectations {
+                    if let Some(failure) = check_json(&json, expected) {
+                        failures.push(failure);
+                    }
+                }
+            }
+            Err(err) => failures.push(diff("json body", "valid JSON", &format!("{} ({})", preview(body), err))),
+        }
+    }
+
+    failures
+}
+
+/// Checks a single `<jsonpath>=<value>` expectation, the value is compared as
+/// JSON when it parses as JSON and as a string otherwise.
+pub fn check_json(json: &Value, expectation: &str) -> Option<String> {
+    let (path, value) = expectation
+        .split_once('=')
+        .unwrap_or_else(|| panic!("Unexpected --expect-json format {}", expectation));
+    let path = path.trim();
+    let expected = parse_expected(value.trim());
+    match jsonpath::select(json, path) {
+        Ok(found) if found.iter().any(|v| **v == expected) => None,
+        Ok(found) if found.is_empty() => Some(diff(path, &expected.to_string(), "<no match>")),
+        Ok(found) => {
+            let actual: Vec<String> = found.iter().map(|v| v.to_string()).collect();
+            Some(diff(path, &expected.to_string(), &actual.join(", ")))
+        }
+        Err(err) => Some(err),
+    }
+}
+
+pub fn parse_expected(value: &str) -> Value {
+    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+/// Accepts a comma separated list of codes or classes like `2xx`.
+fn status_matches(expected: &str, status: StatusCode) -> bool {
+    let actual = status.as_u16().to_string();
+    expected.split(',').map(|e| e.trim()).any(|e| {
+        e.len() == 3
+            && e.chars()
+                .zip(actual.chars())
+                .all(|(e, a)| e == 'x' || e == 'X' || e == a)
+    })
+}
+
+fn diff(what: &str, expected: &str, actual: &str) -> String {
+    format!("Expectation failed: {}\n- expected: {}\n+ actual:   {}", what, expected, actual)
+}
+
+fn preview(body: &str) -> String {
+    const MAX: usize = 200;
+    let body = body.trim();
+    match body.char_indices().nth(MAX) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body.to_string(),
+    }
+}
@@ -0,0 +1,48 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+/// Races connections to the `resolved` addresses of `host` the way RFC 8305 describes:
+/// IPv6 and IPv4 addresses take turns, starting with IPv6, and each attempt starts `delay`
+/// after the one before. The address of the first socket to connect wins and all the
+/// sockets are given up, the request connects to the winner afresh.
+pub async fn race(host: &str, resolved: Vec<SocketAddr>, delay: Duration) -> Result<SocketAddr, String> {
+    let (v6, v4): (Vec<SocketAddr>, Vec<SocketAddr>) = resolved.into_iter().partition(|a| a.is_ipv6());
+    let mut addresses = Vec::new();
+    for i in 0..v6.len().max(v4.len()) {
+        addresses.extend(v6.get(i));
+        addresses.extend(v4.get(i));
+    }
+    if addresses.is_empty() {
+        return Err(format!("{} has no addresses", host));
+    }
+
+    let (sender, mut receiver) = mpsc::channel(addresses.len());
+    let mut attempts = Vec::new();
+    for (i, address) in addresses.iter().copied().enumerate() {
+        let sender = sender.clone();
+        attempts.push(tokio::spawn(async move {
+            tokio::time::sleep(delay * i as u32).await;
+            let result = TcpStream::connect(address).await.map(drop);
+            let _ = sender.send((address, result)).await;
+        }));
+    }
+    drop(sender);
+
+    let mut errors = Vec::new();
+    let mut winner = None;
+    while let Some((remote, result)) = receiver.recv().await {
+        match result {
+            Ok(()) => {
+                winner = Some(remote);
+                break;
+            }
+            Err(err) => errors.push(format!("{}: {}", remote, err)),
+        }
+    }
+    for attempt in attempts {
+        attempt.abort();
+    }
+    winner.ok_or_else(|| format!("Could not connect to {}: {}", host, errors.join(", ")))
+}
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+use clap::ArgMatches;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Method, Url};
+use serde_json::Value;
+use tokio::sync::Semaphore;
+use crate::template::{self, Vars};
+use crate::txlog::Transaction;
+use crate::redirect::{self, Policy};
+use crate::{charset, jsonpath};
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// A follow-up request and its decoded body, `None` when the transfer failed.
+pub struct Followup {
+    pub transaction: Transaction,
+    pub body: Option<String>,
+}
+
+/// Placeholders for one element of the fan-out list. Objects expose their fields,
+/// other values are named after the last key of the path, e.g. `{{id}}` for `$.items[*].id`.
+pub fn element_vars(path: &str, element: &Value, vars: &Vars) -> Vars {
+    let mut vars = vars.clone();
+    match element {
+        Value::Object(map) => {
+            for (key, value) in map {
+                vars.insert(key.clone(), as_text(value));
+            }
+        }
+        other => {
+            let name = path
+                .rsplit('.')
+                .next()
+                .filter(|key| key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-'))
+                .filter(|key| !key.is_empty())
+                .unwrap_or("value");
+            vars.insert(name.to_string(), as_text(other));
+        }
+    }
+    vars
+}
+
+fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Splits `--then` into the method and the url, a bare url means GET.
+fn parse_then(then: &str) -> (Method, &str) {
+    let then = then.trim();
+    match then.split_once(char::is_whitespace) {
+        Some((method, url)) => (
+            Method::from_bytes(method.to_uppercase().as_bytes())
+                .unwrap_or_else(|_| panic!("Invalid --then method {}", method)),
+            url.trim(),
+        ),
+        None => (Method::GET, then),
+    }
+}
+
+/// Sends the `--then` request once per element the `--fan-out` path selects in `body`,
+/// at most `--fan-out-concurrency` at a time. Results are in the order of the elements.
+/// Fails when `body` is not JSON.
+pub async fn run(
+    matches: &ArgMatches,
+    client: &Client,
+    base: &Url,
+    body: &str,
+    vars: &Vars,
+) -> Result<Vec<Followup>, String> {
+    let path = matches.value_of("fan-out").unwrap();
+    let json: Value = serde_json::from_str(body)
+        .map_err(|err| format!("--fan-out needs a JSON response from {}: {}", base, err))?;
+    let elements = jsonpath::select(&json, path).unwrap_or_else(|err| panic!("{}", err));
+    let (method, url) = parse_then(matches.value_of("then").unwrap());
+    let concurrency = matches
+        .value_of("fan-out-concurrency")
+        .map(|c| c.parse::<usize>().expect("--fan-out-concurrency must be a number"))
+        .unwrap_or(DEFAULT_CONCURRENCY)
+        .max(1);
+    let permits = Arc::new(Semaphore::new(concurrency));
+    let forced = charset::forced(matches);
+
+    let mut tasks = Vec::new();
+    for element in elements {
+        let vars = element_vars(path, element, vars);
+        let url = base
+            .join(&template::render(url, &vars))
+            .unwrap_or_else(|err| panic!("Invalid --then url: {}", err));
+        let req = client
+            .request(method.clone(), url)
+            .headers(crate::parse_headers(matches, &vars))
+            .build()
+            .unwrap();
+        let client = client.clone();
+        let policy = Policy::from_matches(matches);
+        let permits = permits.clone();
+        tasks.push(tokio::spawn(async move {
+            let _permit = permits.acquire().await.unwrap();
+            let mut transaction = Transaction::start(&req);
+            let body = match redirect::send(&client, req, &policy).await {
+                Ok(response) => {
+                    transaction.headers_received(response.status().as_u16());
+                    let headers: HeaderMap = response.headers().clone();
+                    match response.bytes().await {
+                        Ok(bytes) => {
+                            transaction.body_received(bytes.len());
+                            Some(charset::decode(&headers, &bytes, forced))
+                        }
+                        Err(err) => {
+                            transaction.failed(&err);
+                            None
+                        }
+                    }
+                }
+                Err(err) => {
+                    transaction.failed(&err);
+                    None
+                }
+            };
+            Followup { transaction, body }
+        }));
+    }
+
+    let mut followups = Vec::new();
+    for task in tasks {
+        followups.push(task.await.expect("Follow-up request panicked"));
+    }
+    Ok(followups)
+}
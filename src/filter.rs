@@ -0,0 +1,53 @@
+use colored::Colorize;
+use regex::Regex;
+
+/// Keeps the lines of `text` that match `pattern` plus `context` lines around
+/// them, separating non-adjacent groups with `--` like grep does.
+pub fn grep(text: &str, pattern: &Regex, context: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let hits: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| pattern.is_match(line))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut out: Vec<String> = Vec::new();
+    let mut next = 0;
+    for hit in hits {
+        let start = hit.saturating_sub(context).max(next);
+        let end = (hit + context + 1).min(lines.len());
+        if start >= end {
+            continue;
+        }
+        if !out.is_empty() && start > next {
+            out.push("--".cyan().to_string());
+        }
+        for line in &lines[start..end] {
+            out.push(highlight_matches(line, pattern));
+        }
+        next = end;
+    }
+    out.join("\n")
+}
+
+fn highlight_matches(line: &str, pattern: &Regex) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for m in pattern.find_iter(line) {
+        out.push_str(&line[last..m.start()]);
+        out.push_str(&m.as_str().red().bold().to_string());
+        last = m.end();
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+pub fn head_lines(text: &str, count: usize) -> String {
+    text.lines().take(count).collect::<Vec<&str>>().join("\n")
+}
+
+pub fn tail_lines(text: &str, count: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    lines[lines.len().saturating_sub(count)..].join("\n")
+}
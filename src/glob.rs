@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+use regex::Regex;
+
+/// How many urls one globbed url may expand to, so a typo like `[1-1000000000]` fails early
+/// instead of filling the memory.
+pub const MAX_URLS: u64 = 100_000;
+
+/// A url with its globs expanded, and the text each glob produced in it.
+pub struct Expanded {
+    pub url: String,
+    pub parts: Vec<String>,
+}
+
+enum Segment {
+    Literal(String),
+    Choices(Vec<String>),
+}
+
+/// Expands curl style globs in `url`: `{a,b}` alternatives and `[1-10]`, `[01-10:2]` or
+/// `[a-z]` ranges. `{{name}}` placeholders and brackets that hold no range, like an IPv6
+/// address, are left alone. The first glob changes slowest.
+pub fn expand(url: &str) -> Result<Vec<Expanded>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut rest = url;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("{{") {
+            let end = rest.find("}}").map(|i| i + 2).unwrap_or(rest.len());
+            literal.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let choices = match c {
+            '{' => {
+                let end = rest.find('}').ok_or_else(|| format!("Unmatched {{ in {}", url))?;
+                Some((rest[1..end].split(',').map(|s| s.to_string()).collect(), end))
+            }
+            '[' => match rest.find(']') {
+                Some(end) => range(&rest[1..end])?.map(|values| (values, end)),
+                None => None,
+            },
+            _ => None,
+        };
+        match choices {
+            Some((values, end)) => {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                segments.push(Segment::Choices(values));
+                rest = &rest[end + 1..];
+            }
+            None => {
+                literal.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    segments.push(Segment::Literal(literal));
+    let total = segments.iter().try_fold(1u64, |total, segment| match segment {
+        Segment::Literal(_) => Some(total),
+        Segment::Choices(values) => total.checked_mul(values.len() as u64),
+    });
+    if total.is_none_or(|total| total > MAX_URLS) {
+        return Err(format!("{} expands to more than {} urls", url, MAX_URLS));
+    }
+
+    let mut expanded = vec![Expanded { url: String::new(), parts: Vec::new() }];
+    for segment in &segments {
+        expanded = match segment {
+            Segment::Literal(text) => expanded
+                .into_iter()
+                .map(|e| Expanded { url: e.url + text, parts: e.parts })
+                .collect(),
+            Segment::Choices(values) => expanded
+                .iter()
+                .flat_map(|e| {
+                    values.iter().map(move |value| Expanded {
+                        url: e.url.clone() + value,
+                        parts: e.parts.iter().cloned().chain([value.clone()]).collect(),
+                    })
+                })
+                .collect(),
+        };
+    }
+    Ok(expanded)
+}
+
+/// The values of a `[start-end:step]` range, `None` when `spec` is no range at all.
+fn range(spec: &str) -> Result<Option<Vec<String>>, String> {
+    let pattern = Regex::new(r"^(?:(\d+)-(\d+)|([a-zA-Z])-([a-zA-Z]))(?::(\d+))?$").unwrap();
+    let captures = match pattern.captures(spec) {
+        Some(captures) => captures,
+        None => return Ok(None),
+    };
+    let step: usize = match captures.get(5) {
+        Some(step) => step.as_str().parse().map_err(|_| format!("Invalid step in [{}]", spec))?,
+        None => 1,
+    };
+    if step == 0 {
+        return Err(format!("The step in [{}] must not be 0", spec));
+    }
+    if let (Some(start), Some(end)) = (captures.get(1), captures.get(2)) {
+        let width = if start.as_str().starts_with('0') { start.as_str().len() } else { 0 };
+        let start: u64 = start.as_str().parse().map_err(|_| format!("Invalid range [{}]", spec))?;
+        let end: u64 = end.as_str().parse().map_err(|_| format!("Invalid range [{}]", spec))?;
+        if start > end {
+            return Err(format!("The range [{}] counts backwards", spec));
+        }
+        if (end - start) / step as u64 >= MAX_URLS {
+            return Err(format!("The range [{}] expands to more than {} urls", spec, MAX_URLS));
+        }
+        return Ok(Some((start..=end).step_by(step).map(|n| format!("{:0width$}", n, width = width)).collect()));
+    }
+    let start = captures[3].chars().next().unwrap();
+    let end = captures[4].chars().next().unwrap();
+    if start > end || start.is_ascii_lowercase() != end.is_ascii_lowercase() {
+        return Err(format!("Invalid range [{}]", spec));
+    }
+    Ok(Some((start..=end).step_by(step).map(|c| c.to_string()).collect()))
+}
+
+/// Replaces `#1`, `#2` ... in an -o template with the text of that glob.
+pub fn fill(template: &str, parts: &[String]) -> String {
+    let pattern = Regex::new(r"#(\d+)").unwrap();
+    pattern
+        .replace_all(template, |captures: &regex::Captures| {
+            captures[1]
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|i| parts.get(i))
+                .cloned()
+                .unwrap_or_else(|| captures[0].to_string())
+        })
+        .to_string()
+}
+
+/// The last path segment of `url`, what --remote-name-all saves it as.
+pub fn remote_name(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let path = path.split_once("://").map(|(_, rest)| rest).unwrap_or(path);
+    let (_, path) = path.split_once('/')?;
+    path.rsplit('/').next().filter(|name| !name.is_empty()).map(|name| name.to_string())
+}
+
+/// `path`, or with --no-clobber the first of `path.1`, `path.2` ... that does not exist yet.
+pub fn unclobbered(path: &Path) -> PathBuf {
+    let mut candidate = path.to_path_buf();
+    let mut n = 1;
+    while candidate.exists() {
+        candidate = PathBuf::from(format!("{}.{}", path.display(), n));
+        n += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn urls(url: &str) -> Vec<String> {
+        expand(url).unwrap().into_iter().map(|e| e.url).collect()
+    }
+
+    #[test]
+    fn expands_alternatives_and_ranges_in_order() {
+        assert_eq!(urls("http://h/{a,b}/[1-2]"), ["http://h/a/1", "http://h/a/2", "http://h/b/1", "http://h/b/2"]);
+        assert_eq!(urls("http://h/[08-10]"), ["http://h/08", "http://h/09", "http://h/10"]);
+        assert_eq!(urls("http://h/[a-e:2]"), ["http://h/a", "http://h/c", "http://h/e"]);
+    }
+
+    #[test]
+    fn leaves_placeholders_and_addresses_alone() {
+        assert_eq!(urls("http://[::1]:8080/{{id}}"), ["http://[::1]:8080/{{id}}"]);
+    }
+
+    #[test]
+    fn rejects_bad_globs() {
+        assert!(expand("http://h/{a,b").is_err());
+        assert!(expand("http://h/[5-1]").is_err());
+        assert!(expand("http://h/[1-5:0]").is_err());
+        assert!(expand("http://h/[a-Z]").is_err());
+    }
+
+    #[test]
+    fn caps_the_number_of_urls() {
+        assert_eq!(expand("http://h/[1-100000]").unwrap().len(), 100_000);
+        assert!(expand("http://h/[1-100001]").is_err());
+        assert!(expand("http://h/[0-18446744073709551615]").is_err());
+        assert!(expand("http://h/[1-1000]/[1-1000]").is_err());
+    }
+
+    #[test]
+    fn fills_output_templates() {
+        let expanded = expand("http://h/{x,y}/[1-3]").unwrap();
+        assert_eq!(fill("page_#1_#2.html", &expanded[4].parts), "page_y_2.html");
+        assert_eq!(fill("#3", &expanded[0].parts), "#3");
+    }
+
+    #[test]
+    fn remote_name_is_the_last_segment() {
+        assert_eq!(remote_name("http://h/a/b.txt?x=1").as_deref(), Some("b.txt"));
+        assert_eq!(remote_name("http://h/a/"), None);
+        assert_eq!(remote_name("http://h"), None);
+    }
+
+    proptest! {
+        #[test]
+        fn expand_never_panics(url in any::<String>()) {
+            let _ = expand(&url);
+        }
+
+        #[test]
+        fn expand_stays_under_the_cap(url in r"[a-z/{},\[\]0-9:-]{0,40}") {
+            if let Ok(expanded) = expand(&url) {
+                prop_assert!(expanded.len() as u64 <= MAX_URLS);
+            }
+        }
+
+        #[test]
+        fn ranges_count_like_the_spec(start in 0u64..500, len in 0u64..500, step in 1u64..20) {
+            let end = start + len;
+            let expanded = expand(&format!("http://h/[{}-{}:{}]", start, end, step)).unwrap();
+            prop_assert_eq!(expanded.len() as u64, len / step + 1);
+            prop_assert_eq!(&expanded[0].parts[0], &start.to_string());
+        }
+
+        #[test]
+        fn fill_never_panics(template in any::<String>(), parts in proptest::collection::vec(any::<String>(), 0..4)) {
+            let _ = fill(&template, &parts);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::io::{self, IsTerminal};
+use mime::Mime;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+pub const LANGUAGES: [&str; 6] = ["json", "html", "xml", "js", "css", "none"];
+
+/// Picks the language to highlight a body in, from `--highlight` or the content type.
+/// Returns `None` when stdout is not a terminal or colors are turned off.
+pub fn language(headers: &HeaderMap, forced: Option<&str>) -> Option<&'static str> {
+    if !io::stdout().is_terminal() || !colored::control::SHOULD_COLORIZE.should_colorize() {
+        return None;
+    }
+    if let Some(forced) = forced {
+        return LANGUAGES.iter().copied().find(|l| *l == forced).filter(|l| *l != "none");
+    }
+    detect(headers)
+}
+
+/// The language of a body going by its content type alone.
+pub fn detect(headers: &HeaderMap) -> Option<&'static str> {
+    let mime: Mime = headers.get(CONTENT_TYPE)?.to_str().ok()?.parse().ok()?;
+    let suffix = mime.suffix().map(|s| s.as_str());
+    match (mime.type_().as_str(), mime.subtype().as_str(), suffix) {
+        (_, "json", _) | (_, _, Some("json")) => Some("json"),
+        ("text", "html", _) | (_, "xhtml", _) => Some("html"),
+        (_, "xml", _) | (_, _, Some("xml")) => Some("xml"),
+        (_, "javascript", _) | (_, "ecmascript", _) => Some("js"),
+        ("text", "css", _) => Some("css"),
+        _ => None,
+    }
+}
+
+/// Wraps the text in terminal color codes for the given language.
+pub fn highlight(text: &str, language: &str) -> String {
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let themes = ThemeSet::load_defaults();
+    let syntax = match syntaxes.find_syntax_by_extension(language) {
+        Some(syntax) => syntax,
+        None => return text.to_string(),
+    };
+    let mut highlighter = HighlightLines::new(syntax, &themes.themes["base16-ocean.dark"]);
+    let mut out = String::with_capacity(text.len() * 2);
+    for line in LinesWithEndings::from(text) {
+        match highlighter.highlight_line(line, &syntaxes) {
+            Ok(ranges) => out.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+            Err(_) => return text.to_string(),
+        }
+    }
+    out.push_str("\x1b[0m");
+    out
+}
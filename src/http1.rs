@@ -0,0 +1,243 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use openssl::ssl::{SslConnector, SslMethod};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, TRANSFER_ENCODING};
+use reqwest::{Method, Request, Response, StatusCode, Url};
+
+/// Bytes per chunk of the request body.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// The largest chunk of a chunked response that is accepted.
+const MAX_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How long a read may wait for the server before the exchange fails.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends `req` with a chunked body followed by `trailers`, and reads the answer with the
+/// trailers the server sent after its body.
+pub async fn send_with_trailers(req: &Request, trailers: HeaderMap) -> Result<(Response, HeaderMap), String> {
+    let mut head = format!("{} {} HTTP/1.1\r\n", req.method(), crate::request_target(req.url()));
+    if !req.headers().contains_key("host") {
+        head.push_str(&format!("host: {}\r\n", crate::host_header(req.url()).unwrap_or_default()));
+    }
+    for (name, value) in req.headers().iter().filter(|(name, _)| **name != CONTENT_LENGTH && **name != TRANSFER_ENCODING) {
+        head.push_str(&format!("{}: {}\r\n", name, String::from_utf8_lossy(value.as_bytes())));
+    }
+    let names: Vec<&str> = trailers.keys().map(|name| name.as_str()).collect();
+    head.push_str(&format!(
+        "transfer-encoding: chunked\r\ntrailer: {}\r\nte: trailers\r\nconnection: close\r\n\r\n",
+        names.join(", ")
+    ));
+    let mut message = head.into_bytes();
+    let body = req.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+    for chunk in body.chunks(CHUNK_SIZE) {
+        message.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+        message.extend_from_slice(chunk);
+        message.extend_from_slice(b"\r\n");
+    }
+    message.extend_from_slice(b"0\r\n");
+    for (name, value) in &trailers {
+        message.extend_from_slice(format!("{}: ", name).as_bytes());
+        message.extend_from_slice(value.as_bytes());
+        message.extend_from_slice(b"\r\n");
+    }
+    message.extend_from_slice(b"\r\n");
+    send(req.url(), message, req.method().clone()).await
+}
+
+/// Sends `message` byte for byte to the host of `url`, over TLS for https.
+pub async fn send_raw(url: &Url, message: Vec<u8>) -> Result<(Response, HeaderMap), String> {
+    // Only the method is read from the message, HEAD answers have no body.
+    let method = message
+        .split(|b| b.is_ascii_whitespace())
+        .next()
+        .and_then(|m| Method::from_bytes(m).ok())
+        .unwrap_or(Method::GET);
+    send(url, message, method).await
+}
+
+/// Speaks HTTP/1.1 on a socket of its own for what hyper, under reqwest, will not send.
+/// Redirects are not followed and --cert is not used.
+async fn send(url: &Url, message: Vec<u8>, method: Method) -> Result<(Response, HeaderMap), String> {
+    let url = url.clone();
+    tokio::task::spawn_blocking(move || {
+        let host = url.host_str().ok_or_else(|| format!("Missing host in {}", url))?.to_string();
+        let port = url.port_or_known_default().unwrap_or(80);
+        let tcp = TcpStream::connect((host.trim_matches(['[', ']']), port))
+            .map_err(|err| format!("Could not connect to {}:{}: {}", host, port, err))?;
+        tcp.set_read_timeout(Some(READ_TIMEOUT)).map_err(|err| err.to_string())?;
+        if url.scheme() == "https" {
+            let connector = SslConnector::builder(SslMethod::tls()).map_err(|err| err.to_string())?.build();
+            let stream = connector
+                .connect(host.trim_matches(['[', ']']), tcp)
+                .map_err(|err| format!("TLS handshake with {} failed: {}", host, err))?;
+            exchange(stream, &message, &method)
+        } else {
+            exchange(tcp, &message, &method)
+        }
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+fn exchange(mut stream: impl Read + Write, message: &[u8], method: &Method) -> Result<(Response, HeaderMap), String> {
+    stream.write_all(message).map_err(|err| format!("Could not send the request: {}", err))?;
+    let (status, headers, body, trailers) = read_response(&mut BufReader::new(stream), method)?;
+    let mut response = http::Response::builder().status(status).version(http::Version::HTTP_11);
+    *response.headers_mut().unwrap() = headers;
+    Ok((Response::from(response.body(body).unwrap()), trailers))
+}
+
+/// The status, headers, body and trailers of the answer to a `method` request.
+fn read_response(reader: &mut impl BufRead, method: &Method) -> Result<(StatusCode, HeaderMap, Vec<u8>, HeaderMap), String> {
+    // 100 Continue and other interim responses come before the real one.
+    let (status, headers) = loop {
+        let status_line = read_line(reader)?;
+        let status = std::str::from_utf8(&status_line)
+            .ok()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse::<u16>().ok())
+            .and_then(|code| StatusCode::from_u16(code).ok())
+            .ok_or_else(|| format!("Unexpected status line {:?}", String::from_utf8_lossy(&status_line)))?;
+        let headers = read_headers(reader)?;
+        if !status.is_informational() {
+            break (status, headers);
+        }
+    };
+
+    let no_body = *method == Method::HEAD || matches!(status.as_u16(), 204 | 304);
+    let chunked = headers
+        .get(TRANSFER_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().contains("chunked"));
+    let mut body = Vec::new();
+    let mut trailers = HeaderMap::new();
+    if no_body {
+    } else if chunked {
+        loop {
+            let size_line = String::from_utf8_lossy(&read_line(reader)?).to_string();
+            let size = size_line.split(';').next().unwrap_or_default().trim();
+            let size = u64::from_str_radix(size, 16).map_err(|_| format!("Bad chunk size {:?}", size_line))?;
+            if size == 0 {
+                break;
+            }
+            if size > MAX_CHUNK_SIZE {
+                return Err(format!("Chunk of {} bytes is larger than {} bytes", size, MAX_CHUNK_SIZE));
+            }
+            read_body(reader, size, &mut body)?;
+            read_line(reader)?;
+        }
+        trailers = read_headers(reader)?;
+    } else if let Some(len) = headers.get(CONTENT_LENGTH).and_then(|v| v.to_str().ok()?.parse::<u64>().ok()) {
+        read_body(reader, len, &mut body)?;
+    } else {
+        reader.read_to_end(&mut body).map_err(read_error)?;
+    }
+    Ok((status, headers, body, trailers))
+}
+
+/// Appends the next `len` bytes to `body`. The buffer grows with what arrives rather than
+/// with what the server announced.
+fn read_body(reader: &mut impl BufRead, len: u64, body: &mut Vec<u8>) -> Result<(), String> {
+    let read = reader.take(len).read_to_end(body).map_err(read_error)?;
+    if (read as u64) < len {
+        return Err("The server closed the connection early".to_string());
+    }
+    Ok(())
+}
+
+fn read_error(err: std::io::Error) -> String {
+    match err.kind() {
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+            format!("No data from the server for {} seconds", READ_TIMEOUT.as_secs())
+        }
+        _ => err.to_string(),
+    }
+}
+
+/// A line without its line break. Header values may hold obs-text, so it stays bytes.
+fn read_line(reader: &mut impl BufRead) -> Result<Vec<u8>, String> {
+    let mut line = Vec::new();
+    match reader.read_until(b'\n', &mut line) {
+        Ok(0) => Err("The server closed the connection early".to_string()),
+        Ok(_) => {
+            while line.last().is_some_and(|b| *b == b'\r' || *b == b'\n') {
+                line.pop();
+            }
+            Ok(line)
+        }
+        Err(err) => Err(read_error(err)),
+    }
+}
+
+/// Header lines up to the empty line that ends them.
+fn read_headers(reader: &mut impl BufRead) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        let bad = || format!("Bad header line {:?}", String::from_utf8_lossy(&line));
+        let colon = line.iter().position(|b| *b == b':').ok_or_else(bad)?;
+        let name = HeaderName::from_bytes(line[..colon].trim_ascii()).map_err(|_| bad())?;
+        let value = HeaderValue::from_bytes(line[colon + 1..].trim_ascii()).map_err(|_| bad())?;
+        headers.append(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &[u8], method: Method) -> Result<(StatusCode, HeaderMap, Vec<u8>, HeaderMap), String> {
+        read_response(&mut &raw[..], &method)
+    }
+
+    #[test]
+    fn reads_status_headers_and_body() {
+        let raw = b"HTTP/1.1 100 Continue\r\n\r\n\
+            HTTP/1.1 201 Created\r\nContent-Length: 5\r\nX-Name: caf\xe9\r\n\r\nhello";
+        let (status, headers, body, trailers) = parse(raw, Method::POST).unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(headers["x-name"].as_bytes(), b"caf\xe9");
+        assert_eq!(body, b"hello");
+        assert!(trailers.is_empty());
+    }
+
+    #[test]
+    fn reads_chunked_bodies_and_trailers() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nTrailer: X-Checksum\r\n\r\n\
+            5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Checksum: abc\r\n\r\n";
+        let (_, _, body, trailers) = parse(raw, Method::GET).unwrap();
+        assert_eq!(body, b"hello world");
+        assert_eq!(trailers["x-checksum"], "abc");
+    }
+
+    #[test]
+    fn head_answers_have_no_body() {
+        let (_, headers, body, _) = parse(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n", Method::HEAD).unwrap();
+        assert_eq!(headers[CONTENT_LENGTH], "10");
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_responses() {
+        assert!(parse(b"garbage\r\n\r\n", Method::GET).unwrap_err().starts_with("Unexpected status line"));
+        assert!(parse(b"HTTP/1.1 200 OK\r\nno colon\r\n\r\n", Method::GET).unwrap_err().starts_with("Bad header line"));
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n";
+        assert!(parse(raw, Method::GET).unwrap_err().starts_with("Bad chunk size"));
+        assert!(parse(b"HTTP/1.1 200 OK\r\n", Method::GET).unwrap_err().contains("closed the connection early"));
+    }
+
+    #[test]
+    fn bounds_announced_sizes() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffff\r\nhi";
+        assert!(parse(raw, Method::GET).unwrap_err().starts_with("Chunk of"));
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhi";
+        assert!(parse(raw, Method::GET).unwrap_err().contains("closed the connection early"));
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 18446744073709551615\r\n\r\nhi";
+        assert!(parse(raw, Method::GET).unwrap_err().contains("closed the connection early"));
+    }
+}
@@ -0,0 +1,50 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use tokio::sync::Notify;
+
+/// Exit code after Ctrl-C, 128 + SIGINT like a shell reports it.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static TRANSFERRING: AtomicBool = AtomicBool::new(false);
+
+fn notify() -> &'static Notify {
+    static NOTIFY: OnceLock<Notify> = OnceLock::new();
+    NOTIFY.get_or_init(Notify::new)
+}
+
+/// Takes over Ctrl-C. While a body is being read it is handed to `interrupted`,
+/// so the partial body can be saved; at any other time the process exits at once.
+pub fn install() {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if TRANSFERRING.load(Ordering::SeqCst) {
+                notify().notify_one();
+            } else {
+                eprintln!();
+                process::exit(EXIT_INTERRUPTED);
+            }
+        }
+    });
+}
+
+/// Resolves when Ctrl-C is pressed while a `Transfer` is alive.
+pub async fn interrupted() {
+    notify().notified().await
+}
+
+/// Marks a body as being read until it is dropped.
+pub struct Transfer;
+
+impl Transfer {
+    pub fn start() -> Transfer {
+        TRANSFERRING.store(true, Ordering::SeqCst);
+        Transfer
+    }
+}
+
+impl Drop for Transfer {
+    fn drop(&mut self) {
+        TRANSFERRING.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use serde_json::Value;
+use crate::template::Vars;
+
+/// Loads the rows of an --iterate file. NDJSON is used for `.ndjson`/`.jsonl`
+/// files or when the content starts with `{`, CSV with a header row otherwise.
+pub fn load_rows(path: &str) -> Vec<Vars> {
+    let content = fs::read_to_string(path).expect("Could not read the iterate file");
+    if path.ends_with(".ndjson") || path.ends_with(".jsonl") || content.trim_start().starts_with('{') {
+        ndjson_rows(&content)
+    } else {
+        csv_rows(&content)
+    }
+}
+
+fn csv_rows(content: &str) -> Vec<Vars> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let columns: Vec<String> = reader
+        .headers()
+        .expect("Invalid CSV header row")
+        .iter()
+        .map(|c| c.trim().to_string())
+        .collect();
+    reader
+        .records()
+        .map(|record| {
+            let record = record.expect("Invalid CSV row");
+            columns
+                .iter()
+                .cloned()
+                .zip(record.iter().map(|v| v.to_string()))
+                .collect()
+        })
+        .collect()
+}
+
+fn ndjson_rows(content: &str) -> Vec<Vars> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let row: Value = serde_json::from_str(line).expect("Invalid NDJSON row");
+            let object = row.as_object().expect("NDJSON rows must be objects");
+            object
+                .iter()
+                .map(|(k, v)| {
+                    let value = match v {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    (k.clone(), value)
+                })
+                .collect()
+        })
+        .collect()
+}
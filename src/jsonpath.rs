@@ -0,0 +1,111 @@
+use serde_json::Value;
+
+enum Step {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+/// Evaluates a small JSONPath subset: `$`, `.key`, `['key']`, `[0]`, `[*]` and `.*`.
+pub fn select<'a>(root: &'a Value, path: &str) -> Result<Vec<&'a Value>, String> {
+    let mut current = vec![root];
+    for step in parse(path)? {
+        let mut next = Vec::new();
+        for value in current {
+            match (&step, value) {
+                (Step::Key(key), Value::Object(map)) => next.extend(map.get(key)),
+                (Step::Index(i), Value::Array(items)) => next.extend(items.get(*i)),
+                (Step::Wildcard, Value::Array(items)) => next.extend(items.iter()),
+                (Step::Wildcard, Value::Object(map)) => next.extend(map.values()),
+                _ => {}
+            }
+        }
+        current = next;
+    }
+    Ok(current)
+}
+
+fn parse(path: &str) -> Result<Vec<Step>, String> {
+    let path = path.trim();
+    let rest = path
+        .strip_prefix('$')
+        .ok_or_else(|| format!("JSONPath {} must start with $", path))?;
+    let chars: Vec<char> = rest.chars().collect();
+    let mut steps = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '.' => {
+                let start = i + 1;
+                let mut end = start;
+                while end < chars.len() && chars[end] != '.' && chars[end] != '[' {
+                    end += 1;
+                }
+                let key: String = chars[start..end].iter().collect();
+                match key.as_str() {
+                    "" => return Err(format!("Empty key in JSONPath {}", path)),
+                    "*" => steps.push(Step::Wildcard),
+                    _ => steps.push(Step::Key(key)),
+                }
+                i = end;
+            }
+            '[' => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| *c == ']')
+                    .map(|p| p + i)
+                    .ok_or_else(|| format!("Unclosed [ in JSONPath {}", path))?;
+                let inner: String = chars[i + 1..end].iter().collect();
+                let inner = inner.trim();
+                if inner == "*" {
+                    steps.push(Step::Wildcard);
+                } else if let Some(key) = inner
+                    .strip_prefix('\'')
+                    .and_then(|k| k.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"').and_then(|k| k.strip_suffix('"')))
+                {
+                    steps.push(Step::Key(key.to_string()));
+                } else {
+                    let index = inner
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid index [{}] in JSONPath {}", inner, path))?;
+                    steps.push(Step::Index(index));
+                }
+                i = end + 1;
+            }
+            c => return Err(format!("Unexpected '{}' in JSONPath {}", c, path)),
+        }
+    }
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn selects_keys_indices_and_wildcards() {
+        let doc = json!({"items": [{"id": 1}, {"id": 2}], "a b": true});
+        assert_eq!(select(&doc, "$.items[1].id").unwrap(), vec![&json!(2)]);
+        assert_eq!(select(&doc, "$.items[*].id").unwrap(), vec![&json!(1), &json!(2)]);
+        assert_eq!(select(&doc, "$['a b']").unwrap(), vec![&json!(true)]);
+        assert!(select(&doc, "$.missing").unwrap().is_empty());
+        assert!(select(&doc, "items").is_err());
+        assert!(select(&doc, "$.items[").is_err());
+        assert!(select(&doc, "$.items[x]").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn select_never_panics(path in any::<String>()) {
+            let _ = select(&json!({"a": [1, {"b": 2}]}), &path);
+        }
+
+        #[test]
+        fn select_never_panics_on_path_like_input(path in "\\$[.\\[\\]*'\"a-z0-9]{0,30}") {
+            let _ = select(&json!({"a": [1, {"b": 2}]}), &path);
+        }
+    }
+}
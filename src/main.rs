@@ -1,222 +1,1707 @@
 extern crate core;
 
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs;
+use std::fs::{File, OpenOptions};
 use std::io;
-use std::io::Write;
-use std::path::PathBuf;
-use clap::{Arg, ArgMatches, Command};
-use reqwest::{Request, Response, StatusCode};
-use reqwest::header::{HeaderMap};
+use std::io::{IsTerminal, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use clap::ArgMatches;
+use clap_complete::Shell;
+use reqwest::{Client, Identity, Method, Proxy, Request, Response, StatusCode, Url};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, LAST_MODIFIED, RANGE};
 use colored::Colorize;
+use regex::Regex;
+use checksum::Checksum;
+use document::RequestInfo;
+use otel::Span;
+use profile::Profile;
+use report::TestCase;
+use sign::HmacSign;
+use template::Vars;
+use trace::Tracer;
+use txlog::Transaction;
 
-fn print_req(req: &Request) {
+mod certinfo;
+mod charset;
+mod checksum;
+mod chunks;
+mod compress;
+mod cli;
+mod dns;
+mod document;
+mod expect;
+mod eyeballs;
+mod fanout;
+mod filter;
+mod glob;
+mod http1;
+mod interrupt;
+mod highlight;
+mod iterate;
+mod jsonpath;
+mod otel;
+mod parse;
+mod pretty;
+mod profile;
+mod redirect;
+mod report;
+mod runner;
+mod script;
+mod sign;
+mod ssh;
+mod template;
+mod trace;
+mod txlog;
+
+const SENSITIVE_HEADERS: [&str; 4] = ["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
+struct Redactor {
+    headers: Vec<String>,
+    show_secrets: bool,
+}
+
+impl Redactor {
+    /// Masks the credential and cookie headers plus `extra`.
+    fn new(extra: Vec<String>, show_secrets: bool) -> Redactor {
+        let mut headers: Vec<String> = SENSITIVE_HEADERS.iter().map(|h| h.to_string()).collect();
+        headers.extend(extra);
+        Redactor {
+            headers,
+            show_secrets,
+        }
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Redactor {
+        Redactor::new(
+            matches
+                .values_of("redact-header")
+                .unwrap_or_default()
+                .map(|h| h.trim().to_lowercase())
+                .collect(),
+            matches.is_present("show-secrets"),
+        )
+    }
+
+    fn is_sensitive(&self, name: &str) -> bool {
+        !self.show_secrets && self.headers.iter().any(|h| h.eq_ignore_ascii_case(name))
+    }
+
+    fn display<'a>(&self, name: &str, value: &'a str) -> &'a str {
+        if self.is_sensitive(name) {
+            "<redacted>"
+        } else {
+            value
+        }
+    }
+}
+
+/// The path and query as they appear on the request line.
+fn request_target(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
+
+/// The Host header value, with the port only when it is not the scheme's default.
+fn host_header(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    match url.port() {
+        Some(port) => Some(format!("{}:{}", host, port)),
+        None => Some(host.to_string()),
+    }
+}
+
+fn print_req(req: &Request, redactor: &Redactor) {
     println!(
         "> {} {:?} {}",
         req.method(),
         req.version(),
-        req.url().path()
+        request_target(req.url())
     );
-    println!("> Host: {}", req.url().host().unwrap());
+    if !req.headers().contains_key("host") {
+        if let Some(host) = host_header(req.url()) {
+            println!("> Host: {}", host);
+        }
+    }
     let req_headers = req.headers();
     for (k, v) in req_headers {
-        println!("> {}: {}", k, v.to_str().unwrap())
+        println!("> {}: {}", k, redactor.display(k.as_str(), &String::from_utf8_lossy(v.as_bytes())))
     }
     println!(">")
 }
 
+/// Shows a --raw-request file like print_req shows a built request, the body below the head.
+fn print_raw_request(raw: &[u8]) {
+    let text = String::from_utf8_lossy(raw);
+    let (head, body) = text
+        .split_once("\r\n\r\n")
+        .or_else(|| text.split_once("\n\n"))
+        .unwrap_or((&text, ""));
+    for line in head.lines() {
+        println!("> {}", line);
+    }
+    println!(">");
+    if !body.is_empty() {
+        print_body(body.as_bytes());
+    }
+}
+
+fn print_body(body: &[u8]) {
+    match std::str::from_utf8(body) {
+        Ok(text) => println!("{}", text),
+        Err(_) => println!("<{} bytes of binary data>", body.len()),
+    }
+}
+
+/// Parses a URI, assuming http:// when it does not start with a scheme.
+fn parse_url(uri: &str) -> Result<Url, String> {
+    let scheme = Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*://").unwrap();
+    let uri = if scheme.is_match(uri) {
+        uri.to_string()
+    } else {
+        format!("http://{}", uri)
+    };
+    Url::parse(&uri).map_err(|err| format!("Invalid url {}: {}", uri, err))
+}
+
+/// Shows the punycode form that is actually resolved next to an IDN host.
+fn print_idn(url: &Url) {
+    if let Some(host) = url.host_str() {
+        let (unicode, result) = idna::domain_to_unicode(host);
+        if result.is_ok() && unicode != host {
+            println!("* Internationalized host {} is sent as {}", unicode, host);
+        }
+    }
+}
+
 fn highlight_status_code(code: &StatusCode) -> String {
     if code.to_string().starts_with('2') {
-        return format!("{}", code.to_string().green());
+        format!("{}", code.to_string().green())
     } else if code.to_string().starts_with('3') {
-        return format!("{}", code.to_string().yellow());
+        format!("{}", code.to_string().yellow())
     } else if code.to_string().starts_with('4') {
-        return format!("{}", code.to_string().red());
+        format!("{}", code.to_string().red())
     } else if code.to_string().starts_with('5'){
-        return format!("{}", code.to_string().red().bold());
+        format!("{}", code.to_string().red().bold())
     } else {
         String::new()
     }
 }
 
-fn print_res(res: &Response) {
+fn print_res(res: &Response, redactor: &Redactor) {
     println!(
         "< {:?} {}", res.version(), highlight_status_code(&res.status())
     );
     let res_headers = res.headers();
     for (k, v) in res_headers {
-        println!("< {}: {}", k, v.to_str().unwrap());
+        println!("< {}: {}", k, redactor.display(k.as_str(), &String::from_utf8_lossy(v.as_bytes())));
     }
 
     println!("<")
 }
 
-fn parse_headers(matches: &ArgMatches) -> HeaderMap {
-    if matches.is_present("header") {
-        return HeaderMap::new();
+fn parse_headers(matches: &ArgMatches, vars: &Vars) -> HeaderMap {
+    let mut header_map = HeaderMap::new();
+    for header in matches.values_of("header").unwrap_or_default() {
+        let (k, v) = parse::header(&template::render(header, vars)).unwrap_or_else(|err| panic!("{}", err));
+        header_map.insert(k, v);
     }
+    header_map
+}
+fn parse_trailers(matches: &ArgMatches, vars: &Vars) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    for trailer in matches.values_of("trailer").unwrap_or_default() {
+        let (k, v) = parse::header(&template::render(trailer, vars)).unwrap_or_else(|err| panic!("{}", err));
+        trailers.append(k, v);
+    }
+    trailers
+}
+
+fn parse_fields(matches: &ArgMatches, vars: &Vars) -> HashMap<String, String> {
     let mut header_map = HashMap::new();
-    let headers: Vec<&str> = matches.values_of("header").unwrap_or_default().collect();
+    for field in matches.values_of("form").unwrap_or_default() {
+        let (k, v) = parse::form_field(field).unwrap_or_else(|err| panic!("{}", err));
+        header_map.insert(k, template::render(&v, vars));
+    }
+    header_map
+}
 
-    for header in headers {
-        let values: Vec<&str> = header.split(':').collect();
-        if values.len() != 2 {
-            panic!("Unexpected header format {}", header);
+/// The identity from -E and its flags, or else from the cert settings of the host profile.
+fn load_identity(matches: &ArgMatches, profile: Option<&Profile>) -> Option<Identity> {
+    let (cert_path, cert_type, key, pass) = match matches.value_of("cert") {
+        Some(cert) => (cert, matches.value_of("cert-type"), matches.value_of("key"), matches.value_of("pass")),
+        None => {
+            let profile = profile?;
+            (
+                profile.cert.as_deref()?,
+                profile.cert_type.as_deref(),
+                profile.key.as_deref(),
+                profile.pass.as_deref(),
+            )
         }
-        let k = values[0].to_string().to_lowercase();
-        let v = values[1].trim_end().to_string();
-        header_map.insert(k, v);
+    };
+    let cert = fs::read(cert_path).expect("Could not read the client certificate");
+    let identity = match cert_type.unwrap_or("PEM").to_uppercase().as_str() {
+        "P12" => Identity::from_pkcs12_der(&cert, pass.unwrap_or_default()),
+        _ => {
+            if pass.is_some() {
+                panic!("Encrypted PEM keys are not supported, convert the identity to P12 to use --pass");
+            }
+            let key = match key {
+                Some(key_path) => fs::read(key_path).expect("Could not read the private key"),
+                None => cert.clone(),
+            };
+            Identity::from_pkcs8_pem(&cert, &key)
+        }
+    };
+    Some(identity.expect("Invalid client certificate"))
+}
+
+/// Accepts either a literal address or the name of a network interface, in
+/// which case its first IPv4 address (or else its first address) is used.
+fn resolve_interface(interface: &str) -> IpAddr {
+    if let Ok(ip) = interface.parse::<IpAddr>() {
+        return ip;
     }
-    (&header_map).try_into().expect("Invalid headers")
+    let addresses: Vec<IpAddr> = if_addrs::get_if_addrs()
+        .expect("Could not list the network interfaces")
+        .into_iter()
+        .filter(|i| i.name == interface)
+        .map(|i| i.ip())
+        .collect();
+    addresses
+        .iter()
+        .find(|ip| ip.is_ipv4())
+        .or_else(|| addresses.first())
+        .copied()
+        .unwrap_or_else(|| panic!("No address found for interface {}", interface))
 }
 
-fn parse_fields(matches: &ArgMatches) -> HashMap<String, String> {
-    if !matches.is_present("form") {
-        return HashMap::new();
+/// The client for requests to `uri`, `None` for the client shared by all urls. With `sni`
+/// that name is pinned to the address of `uri`, with --happy-eyeballs-timeout-ms the host
+/// of `uri` is pinned to the address that connected first. `profile` adds the proxy and
+/// client certificate of the host profile.
+async fn build_client(
+    matches: &ArgMatches,
+    uri: Option<&Url>,
+    sni: Option<&str>,
+    profile: Option<&Profile>,
+) -> Result<Client, String> {
+    let mut builder = Client::builder();
+    if let Some(identity) = load_identity(matches, profile) {
+        builder = builder.identity(identity);
     }
-    let mut header_map = HashMap::new();
-    let fields: Vec<&str> = matches.values_of("form").unwrap_or_default().collect();
-    for field in fields {
-        let values: Vec<&str> = field.split("=").collect();
-        if values.len() != 2 {
-            panic!("Unexpected form format {}", field)
+    if let Some(proxy) = profile.and_then(|p| p.proxy.as_deref()) {
+        builder = builder.proxy(Proxy::all(proxy).unwrap_or_else(|err| panic!("Invalid proxy {}: {}", proxy, err)));
+    }
+    // Only the source address can be chosen: reqwest's connector binds it with port 0, so
+    // there is no --local-port to go with it.
+    if let Some(interface) = matches.value_of("interface") {
+        let local = resolve_interface(interface);
+        if matches.is_present("verbose") {
+            println!("* Binding to local address {}", local);
         }
-        let k = values[0].to_string();
-        let v = values[1].trim_start().to_string();
-
-        header_map.insert(k, v);
+        builder = builder.local_address(local);
     }
-    header_map
+    if matches.is_present("no-keepalive") {
+        builder = builder.pool_max_idle_per_host(0);
+    }
+    if let Some(seconds) = matches.value_of("keepalive-time") {
+        let seconds = seconds.parse().expect("--keepalive-time must be a number of seconds");
+        builder = builder.tcp_keepalive(Duration::from_secs(seconds));
+    }
+    if matches.is_present("tcp-nodelay") || matches.is_present("no-tcp-nodelay") {
+        builder = builder.tcp_nodelay(matches.is_present("tcp-nodelay"));
+    }
+    let resolver = dns::shared(matches);
+    builder = builder.dns_resolver(Arc::new(resolver.clone()));
+    let mut pinned = None;
+    let race = matches.value_of("happy-eyeballs-timeout-ms").filter(|_| !matches.is_present("dry-run"));
+    // Addresses need no race, and only names can be pinned with resolve.
+    let target = uri.and_then(|uri| Some((uri.domain()?, uri.port_or_known_default()?)));
+    if let (Some(delay), Some((host, port))) = (race, target) {
+        let delay = Duration::from_millis(delay.parse().expect("--happy-eyeballs-timeout-ms must be a number"));
+        let resolved = resolver.lookup(host).await?;
+        let resolved = resolved.into_iter().map(|ip| SocketAddr::new(ip, port)).collect();
+        let winner = eyeballs::race(host, resolved, delay).await?;
+        if matches.is_present("verbose") {
+            let family = if winner.is_ipv6() { "IPv6" } else { "IPv4" };
+            println!("* Happy eyeballs: {} ({}) connected first", winner, family);
+        }
+        builder = builder.resolve(host, winner);
+        pinned = Some(winner);
+    }
+    if let (Some(sni), Some(uri)) = (sni.filter(|_| !matches.is_present("dry-run")), uri) {
+        let address = match pinned {
+            Some(address) => address,
+            None => {
+                let host = uri.host_str().unwrap_or_default();
+                let ip = resolver
+                    .lookup(host)
+                    .await
+                    .and_then(|ips| ips.first().copied().ok_or_else(|| format!("Could not resolve {}: no addresses", host)))?;
+                SocketAddr::new(ip, uri.port_or_known_default().unwrap_or(443))
+            }
+        };
+        if matches.is_present("verbose") {
+            println!("* Connecting to {} for {}", address, sni);
+        }
+        builder = builder.resolve(sni, address);
+    }
+    // Redirects are followed by redirect::send.
+    builder = builder.redirect(reqwest::redirect::Policy::none());
+    Ok(builder.build().expect("Could not create the http client"))
 }
 
-fn parse_data(matches: &ArgMatches) -> String {
+fn parse_data(matches: &ArgMatches, vars: &Vars) -> String {
     if !matches.is_present("data") {
         return String::new();
     }
     let fields: Vec<&str> = matches.values_of("data").unwrap_or_default().collect();
-    fields.join("&").to_string()
+    template::render(&fields.join("&"), vars)
+}
+
+const FRONTMATTER_HEADERS: [&str; 5] = [
+    "content-type",
+    "content-length",
+    "last-modified",
+    "cache-control",
+    "server",
+];
+
+fn render_frontmatter(url: &str, status: &StatusCode, headers: &HeaderMap) -> String {
+    let mut lines = vec![
+        "---".to_string(),
+        format!("url: {}", url),
+        format!("date: {}", httpdate::fmt_http_date(SystemTime::now())),
+        format!("status: {}", status.as_u16()),
+    ];
+    if let Some(etag) = headers.get("etag").and_then(|v| v.to_str().ok()) {
+        lines.push(format!("etag: '{}'", etag.replace('\'', "''")));
+    }
+    let subset: Vec<(&str, &str)> = FRONTMATTER_HEADERS
+        .iter()
+        .filter_map(|name| {
+            headers
+                .get(*name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| (*name, v))
+        })
+        .collect();
+    if !subset.is_empty() {
+        lines.push("headers:".to_string());
+        for (name, value) in subset {
+            lines.push(format!("  {}: '{}'", name, value.replace('\'', "''")));
+        }
+    }
+    lines.push("---".to_string());
+
+    let mut block = String::new();
+    for line in lines {
+        block.push_str("# ");
+        block.push_str(&line);
+        block.push('\n');
+    }
+    block
+}
+
+fn unescape_separator(raw: &str) -> String {
+    let mut out = String::new();
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('0') => out.push('\0'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
 }
 
-async fn save_in_file(out_path: PathBuf, data: String) -> Result<(), io::Error>{
+async fn save_in_file(out_path: PathBuf, data: &[u8]) -> Result<(), io::Error>{
     let mut file = File::create(out_path)?;
-    file.write_all(data.as_bytes())?;
+    file.write_all(data)?;
+    Ok(())
+}
+
+/// How the body goes into the -o file.
+enum SaveMode {
+    Create,
+    /// --append, with the separator written before the body when the file is not empty.
+    Append(String),
+    /// --continue-at, the body follows the bytes already there.
+    Resume,
+}
+
+impl SaveMode {
+    fn from_matches(matches: &ArgMatches, resumed: bool) -> SaveMode {
+        if resumed {
+            SaveMode::Resume
+        } else if matches.is_present("append") {
+            SaveMode::Append(unescape_separator(matches.value_of("record-separator").unwrap_or_default()))
+        } else {
+            SaveMode::Create
+        }
+    }
+}
+
+/// Keeps what arrived before Ctrl-C in the -o file, written the way `mode` says, and tells
+/// how to pick up from there.
+fn save_interrupted(out: Option<&str>, body: &[u8], mode: &SaveMode) {
+    eprintln!();
+    let path = match out {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", format!("Interrupted after {} bytes", body.len()).red());
+            return;
+        }
+    };
+    let create = matches!(mode, SaveMode::Create);
+    let saved = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(!create)
+        .truncate(create)
+        .open(path)
+        .and_then(|mut file| {
+            if let SaveMode::Append(separator) = mode {
+                if file.metadata()?.len() > 0 {
+                    file.write_all(separator.as_bytes())?;
+                }
+            }
+            file.write_all(body).and_then(|_| file.sync_all())
+        });
+    if let Err(err) = saved {
+        eprintln!("{}", format!("Interrupted after {} bytes, could not save {}: {}", body.len(), path, err).red());
+        return;
+    }
+    eprintln!("{}", format!("Interrupted after {} bytes, kept them in {}", body.len(), path).red());
+    // An appended entry cannot be resumed, --continue-at and --append conflict.
+    if matches!(mode, SaveMode::Append(_)) {
+        return;
+    }
+    let mut args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|a| a == "--continue-at") {
+        args.drain(i..(i + 2).min(args.len()));
+    }
+    args.retain(|a| !a.starts_with("--continue-at="));
+    // Resume the file --no-clobber picked, not the one it kept away from.
+    if args.iter().any(|a| a == "--no-clobber") {
+        args.retain(|a| a != "--no-clobber");
+        if let Some(i) = args.iter().position(|a| a == "-o" || a == "--out-path") {
+            if let Some(value) = args.get_mut(i + 1) {
+                *value = path.to_string();
+            }
+        }
+        for arg in args.iter_mut().filter(|a| a.starts_with("--out-path=")) {
+            *arg = format!("--out-path={}", path);
+        }
+    }
+    args.extend(["--continue-at".to_string(), "-".to_string()]);
+    eprintln!("Resume with: {}", args.iter().map(|a| shell_quote(a)).collect::<Vec<_>>().join(" "));
+}
+
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Gives the saved file the Last-Modified time of the response, if it sent a valid one.
+fn set_remote_time(path: &Path, headers: &HeaderMap) {
+    let modified = headers
+        .get(LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok());
+    if let Some(modified) = modified {
+        File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(modified))
+            .unwrap_or_else(|err| eprintln!("{}", format!("Could not set the time of {}: {}", path.display(), err).red()));
+    }
+}
+
+async fn append_to_file(out_path: PathBuf, data: &[u8], separator: &str) -> Result<(), io::Error> {
+    let mut file = OpenOptions::new().create(true).append(true).open(out_path)?;
+    if file.metadata()?.len() > 0 {
+        file.write_all(separator.as_bytes())?;
+    }
+    file.write_all(data)?;
     Ok(())
 }
 
+/// The --max-response-time and --max-ttfb budgets the transaction went over.
+fn over_budget(matches: &ArgMatches, transaction: &Transaction) -> Vec<String> {
+    let budget = |name: &str| {
+        matches
+            .value_of(name)
+            .map(|ms| Duration::from_millis(ms.parse().unwrap_or_else(|_| panic!("--{} must be a number of milliseconds", name))))
+    };
+    let mut problems = Vec::new();
+    if let Some(max) = budget("max-response-time").filter(|max| transaction.elapsed() > *max) {
+        problems.push(format!(
+            "The response took {:.1} ms, more than the {} ms --max-response-time allows",
+            transaction.elapsed().as_secs_f64() * 1000.0,
+            max.as_millis()
+        ));
+    }
+    if let (Some(max), Some(ttfb)) = (budget("max-ttfb"), transaction.ttfb()) {
+        if ttfb > max {
+            problems.push(format!(
+                "The first byte came after {:.1} ms, more than the {} ms --max-ttfb allows",
+                ttfb.as_secs_f64() * 1000.0,
+                max.as_millis()
+            ));
+        }
+    }
+    problems
+}
+
+/// Logs and exports the finished request and turns it into a report entry.
+/// `failures` holds the failed expectations when --expect-* flags were given.
+async fn finish_transaction(
+    matches: &ArgMatches,
+    transaction: &Transaction,
+    span: Option<&Span>,
+    failures: Option<&[String]>,
+) -> TestCase {
+    if let Some(path) = matches.value_of("log-json") {
+        transaction.append_to(path).expect("Could not write the json log");
+    }
+    if let Some(span) = span {
+        span.export(transaction).await;
+    }
+    let mut case = TestCase::from_transaction(transaction);
+    if let (Some(failures), None) = (failures, transaction.error()) {
+        case.failures = failures.to_vec();
+    }
+    case
+}
+
+/// Exit code when the transfer itself failed, the same as returning an error from main.
+const EXIT_TRANSFER_FAILED: i32 = 1;
+
+const EXIT_EXPECTATION_FAILED: i32 = 90;
+
+/// The response was fine but slower than --max-response-time or --max-ttfb allow.
+const EXIT_TOO_SLOW: i32 = 92;
+
+/// The request file given to `run` could not be read or parsed.
+const EXIT_SCRIPT_INVALID: i32 = 2;
+
+/// The body did not match an --expected-* digest.
+const EXIT_CHECKSUM_MISMATCH: i32 = 91;
+
+/// Same exit code as curl uses when the server will not resume with --continue-at.
+const EXIT_RANGE_ERROR: i32 = 33;
+
+/// Same exit code as curl uses for a malformed url.
+const EXIT_URL_MALFORMED: i32 = 3;
+
+/// Same exit code as curl uses when a file:// url cannot be read.
+const EXIT_FILE_UNREADABLE: i32 = 37;
+
+/// Same exit code as curl uses for --max-filesize.
+const EXIT_FILESIZE_EXCEEDED: i32 = 63;
+
+/// How a single request ended.
+struct Outcome {
+    case: TestCase,
+    status: Option<StatusCode>,
+    exit_code: i32,
+    error: Option<reqwest::Error>,
+    /// The decoded response body, only kept for --fan-out.
+    body: Option<String>,
+    /// Response body bytes received, for --batch-progress.
+    bytes: u64,
+}
+
+async fn abort_max_filesize(
+    matches: &ArgMatches,
+    transaction: &mut Transaction,
+    span: Option<&Span>,
+    status: StatusCode,
+    max: u64,
+) -> Outcome {
+    let message = format!("Maximum file size of {} bytes exceeded", max);
+    transaction.failed(&message);
+    eprintln!("{}", message);
+    Outcome {
+        case: finish_transaction(matches, transaction, span, None).await,
+        status: Some(status),
+        exit_code: EXIT_FILESIZE_EXCEEDED,
+        error: None,
+        body: None,
+        bytes: 0,
+    }
+}
+
+fn parse_size(size: &str) -> u64 {
+    parse::size(size).unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn attach_limit(matches: &ArgMatches) -> usize {
+    matches
+        .value_of("attach-limit")
+        .map(|limit| parse_size(limit) as usize)
+        .unwrap_or(report::DEFAULT_ATTACH_LIMIT)
+}
+
 #[tokio::main]
 async fn main() -> Result<(), reqwest::Error> {
-    let matches = Command::new(
-        env!("CARGO_PKG_NAME")
-    ).version(
-        env!("CARGO_PKG_VERSION")
-    ).about("Cli tool that makes request to the endpoints and processes the responses")
-        .author("BufferOverflow")
-        .arg(
-            Arg::new("verbose")
-                .short('v')
-                .long("verbose")
-                .help("Sets the output level to verbose")
-        )
-        .arg(
-            Arg::new("method")
-                .short('X')
-                .long("method")
-                .takes_value(true)
-                .possible_values(&["POST", "GET", "PUT", "PATCH", "HEAD", "DELETE"])
-                .ignore_case(true)
-                .help("Sets the http method for the request")
-        )
-        .arg(
-            Arg::new("header")
-                .short('H')
-                .multiple_values(true)
-                .takes_value(true)
-                .help("Sets header content for the request")
-        )
-        .arg(
-            Arg::new("form")
-                .short('F')
-                .takes_value(true)
-                .multiple_values(true)
-                .help("Set the form values in a field=value pair")
-        )
-        .arg(
-            Arg::new("data")
-                .short('d')
-                .multiple_values(true)
-                .takes_value(true)
-                .help("Sets the data values and combine from a field=value pair")
-        )
-        .arg(
-            Arg::new("uri")
-                .index(1)
-                .required(true)
-        )
-        .arg(
-            Arg::new("out")
-                .value_name("PATH")
-                .short('o')
-                .long("out-path")
-                .help("Saves the response in the file")
-        ).get_matches();
+    let matches = cli::build().get_matches();
+    interrupt::install();
+
+    report::check_format(&matches);
+
+    match matches.subcommand() {
+        Some(("run", run_matches)) => process::exit(runner::run(run_matches).await),
+        Some(("completions", completion_matches)) => {
+            let shell: Shell = completion_matches.value_of_t("shell").unwrap();
+            clap_complete::generate(shell, &mut cli::build(), env!("CARGO_PKG_NAME"), &mut io::stdout());
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let targets = load_targets(&matches);
+    // Errors are printed as they happen when more than one request is sent.
+    let batch = matches.is_present("iterate") || targets.len() > 1;
+
+    let vars = template::from_matches(&matches);
+    let client = build_client(&matches, None, None, None).await.unwrap();
+
+    let rows = match matches.value_of("iterate") {
+        Some(path) => iterate::load_rows(path)
+            .into_iter()
+            .map(|row| vars.clone().into_iter().chain(row).collect())
+            .collect(),
+        None => vec![vars],
+    };
+
+    let mut outcomes = Vec::new();
+    let mut followups = Vec::new();
+    let mirrors = load_mirrors(&matches);
+    let progress = batch && matches.is_present("batch-progress");
+    let started = Instant::now();
+    let mut received = 0;
+    for ((target, line_out), vars) in targets.iter().flat_map(|t| rows.iter().map(move |r| (t, r))) {
+        let mut uris = vec![target.url.clone()];
+        uris.extend(mirrors.iter().cloned());
+        let out = match line_out.as_deref().or_else(|| matches.value_of("out")) {
+            Some(template) => Some(glob::fill(template, &target.parts)),
+            None if matches.is_present("remote-name-all") => {
+                let url = template::render(&target.url, vars);
+                Some(glob::remote_name(&url).unwrap_or_else(|| panic!("{} has no file name to save as, use -o", url)))
+            }
+            None => None,
+        };
+        // One key per request, shared by its mirror attempts.
+        let idempotency_key = matches
+            .value_of("idempotency-key")
+            .map(|key| if key == "auto" { uuid_v4() } else { template::render(key, vars) });
+        let mut attempt = 0;
+        let outcome = loop {
+            let fallback = attempt + 1 < uris.len();
+            let outcome = perform(
+                &matches,
+                &client,
+                vars,
+                &uris[attempt],
+                fallback,
+                idempotency_key.as_deref(),
+                out.as_deref(),
+            )
+            .await;
+            match fallback.then(|| fallback_reason(&outcome)).flatten() {
+                Some(reason) => {
+                    if matches.is_present("verbose") {
+                        println!("* {} failed: {}, trying mirror {}", uris[attempt], reason, uris[attempt + 1]);
+                    }
+                    attempt += 1;
+                }
+                None => break outcome,
+            }
+        };
+        if let (Some(err), true) = (&outcome.error, batch) {
+            eprintln!("{}", err.to_string().red());
+        }
+        if let Some(body) = &outcome.body {
+            let base = parse_url(&template::render(&uris[attempt], vars)).unwrap();
+            match fanout::run(&matches, &client, &base, body, vars).await {
+                Ok(list) => {
+                    for followup in list {
+                        followups.push(finish_followup(&matches, followup).await);
+                    }
+                }
+                Err(message) => {
+                    eprintln!("{}", message.red());
+                    let mut case = TestCase::new(&format!("--fan-out {}", base));
+                    case.errors.push(message);
+                    followups.push(Outcome { case, status: None, exit_code: EXIT_TRANSFER_FAILED, error: None, body: None, bytes: 0 });
+                }
+            }
+        }
+        received += outcome.bytes;
+        outcomes.push(outcome);
+        if progress {
+            let total = targets.len() * rows.len();
+            print_batch_progress(&matches, outcomes.len(), total, received, started.elapsed());
+        }
+    }
+
+    if let Some(mut report) = matches.values_of("report") {
+        let format = report.next().unwrap();
+        let cases: Vec<&TestCase> = outcomes.iter().chain(followups.iter()).map(|o| &o.case).collect();
+        report::write(format, report.next().unwrap(), &cases).expect("Could not write the report");
+    }
+
+    if matches.is_present("iterate") {
+        print_iteration_summary(&outcomes);
+    }
+
+    let mut exit_code = outcomes
+        .iter()
+        .chain(followups.iter())
+        .map(|o| o.exit_code)
+        .find(|c| *c != 0)
+        .unwrap_or(0);
+    if let Some(min) = matches.value_of("min-success-rate").filter(|_| batch) {
+        let min = parse::percent(min).unwrap_or_else(|err| panic!("{}", err));
+        let transfers: Vec<&Outcome> = outcomes.iter().chain(followups.iter()).collect();
+        exit_code = match success_rate(&transfers, min) {
+            true => 0,
+            // Only 4xx and 5xx answers failed, none of them set an exit code.
+            false if exit_code == 0 => EXIT_EXPECTATION_FAILED,
+            false => exit_code,
+        };
+    }
+    if !batch {
+        if let Some(err) = outcomes.pop().and_then(|o| o.error) {
+            return Err(err);
+        }
+    }
+    if exit_code != 0 {
+        process::exit(exit_code);
+    }
+
+    Ok(())
+}
+
+/// Prints the body of a --fan-out follow-up request and logs it like the first request.
+async fn finish_followup(matches: &ArgMatches, followup: fanout::Followup) -> Outcome {
+    let transaction = followup.transaction;
+    match &followup.body {
+        Some(body) => {
+            let body = body.trim_end();
+            if !body.is_empty() {
+                println!("{}", body);
+            }
+        }
+        None => eprintln!(
+            "{}",
+            format!("{}: {}", transaction.url(), transaction.error().unwrap_or_default()).red()
+        ),
+    }
+    Outcome {
+        case: finish_transaction(matches, &transaction, None, None).await,
+        status: transaction.status().and_then(|s| StatusCode::from_u16(s).ok()),
+        exit_code: if followup.body.is_some() { 0 } else { EXIT_TRANSFER_FAILED },
+        error: None,
+        body: None,
+        bytes: 0,
+    }
+}
+
+/// The overall progress of a batch after `done` of `total` transfers, on stderr so it stays
+/// apart from the bodies. With --output-format json it is a JSON line.
+fn print_batch_progress(matches: &ArgMatches, done: usize, total: usize, bytes: u64, elapsed: Duration) {
+    let seconds = elapsed.as_secs_f64();
+    let speed = if seconds > 0.0 { bytes as f64 / seconds } else { 0.0 };
+    let eta = seconds / done as f64 * (total - done) as f64;
+    if matches.value_of("output-format") == Some("json") {
+        let line = serde_json::json!({
+            "batch": {
+                "done": done,
+                "total": total,
+                "bytes": bytes,
+                "bytes_per_second": speed.round(),
+                "eta_seconds": (eta * 10.0).round() / 10.0,
+            }
+        });
+        eprintln!("{}", line);
+    } else {
+        eprintln!(
+            "Batch: {}/{} done, {} received, {}/s, ETA {:.0}s",
+            done,
+            total,
+            format_bytes(bytes as f64),
+            format_bytes(speed),
+            eta
+        );
+    }
+}
 
-    let uri = matches.value_of("uri").unwrap();
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
 
-    let client = reqwest::Client::new();
+/// Prints the transfers of a batch by status class and returns whether at least `min`
+/// percent of them succeeded: without an error and with a status below 400.
+fn success_rate(transfers: &[&Outcome], min: f64) -> bool {
+    let mut classes = [0; 5];
+    let mut no_response = 0;
+    for transfer in transfers {
+        match transfer.status {
+            Some(status) => classes[(status.as_u16() / 100).clamp(1, 5) as usize - 1] += 1,
+            None => no_response += 1,
+        }
+    }
+    let mut buckets: Vec<String> = classes
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(i, count)| format!("{}xx {}", i + 1, count))
+        .collect();
+    if no_response > 0 {
+        buckets.push(format!("no response {}", no_response));
+    }
+    let succeeded = transfers
+        .iter()
+        .filter(|t| t.exit_code == 0 && t.status.is_some_and(|s| s.as_u16() < 400))
+        .count();
+    let rate = if transfers.is_empty() { 100.0 } else { succeeded as f64 * 100.0 / transfers.len() as f64 };
+    let met = rate >= min;
+    let verdict = if met { "met".green() } else { "not met".red() };
+    eprintln!(
+        "{} transfers: {}; {} succeeded ({:.1}%), --min-success-rate {}% {}",
+        transfers.len(),
+        buckets.join(", "),
+        succeeded,
+        rate,
+        min,
+        verdict
+    );
+    met
+}
+
+fn print_iteration_summary(outcomes: &[Outcome]) {
+    let failed = outcomes
+        .iter()
+        .filter(|o| o.exit_code != 0 || !o.case.errors.is_empty() || !o.case.failures.is_empty())
+        .count();
+    eprintln!();
+    eprintln!("Iteration summary:");
+    for (i, outcome) in outcomes.iter().enumerate() {
+        let status = outcome
+            .status
+            .map(|s| s.as_u16().to_string())
+            .unwrap_or_else(|| "---".to_string());
+        let problems: Vec<&str> = outcome
+            .case
+            .errors
+            .iter()
+            .chain(outcome.case.failures.iter())
+            .filter_map(|p| p.lines().next())
+            .collect();
+        let result = if problems.is_empty() && outcome.exit_code == 0 {
+            "ok".green().to_string()
+        } else {
+            format!("{} {}", "failed".red(), problems.join("; "))
+        };
+        eprintln!(
+            "  row {:<4} {} {:>8.1} ms  {}",
+            i + 1,
+            status,
+            outcome.case.duration.as_secs_f64() * 1000.0,
+            result
+        );
+    }
+    eprintln!("{} rows, {} passed, {} failed", outcomes.len(), outcomes.len() - failed, failed);
+}
+
+fn is_http(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
+
+/// Reads a file:// url into a response, so that local files go through the same output as HTTP bodies.
+fn file_response(url: &Url) -> Result<Response, String> {
+    let path = url
+        .to_file_path()
+        .map_err(|_| format!("Not a local file url {}", url))?;
+    let data = fs::read(&path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    Ok(content_response(url.path(), data))
+}
 
-    let method = matches.value_of("method").unwrap();
+/// Downloads an sftp:// or scp:// url into a response, or uploads the --upload-file there
+/// and answers with an empty one.
+async fn ssh_response(matches: &ArgMatches, url: &Url) -> Result<Response, String> {
+    let target = ssh::Target::new(url, matches)?;
+    match matches.value_of("upload-file") {
+        Some(path) => {
+            let data = fs::read(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+            let len = data.len();
+            tokio::task::spawn_blocking(move || target.upload(&data))
+                .await
+                .map_err(|err| err.to_string())??;
+            println!("Uploaded {} bytes to {}", len, url);
+            Ok(content_response(url.path(), Vec::new()))
+        }
+        None => {
+            let data = tokio::task::spawn_blocking(move || target.download())
+                .await
+                .map_err(|err| err.to_string())??;
+            Ok(content_response(url.path(), data))
+        }
+    }
+}
+
+/// A 200 response holding `data`, with the content type guessed from the extension of `path`.
+fn content_response(path: &str, data: Vec<u8>) -> Response {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    let content_type = match extension.as_deref() {
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("html") | Some("htm") => "text/html",
+        Some("js") => "application/javascript",
+        Some("css") => "text/css",
+        Some("txt") | Some("csv") | Some("md") => "text/plain",
+        _ if std::str::from_utf8(&data).is_ok() => "text/plain",
+        _ => "application/octet-stream",
+    };
+    let response = http::Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, content_type)
+        .header(CONTENT_LENGTH, data.len())
+        .body(data)
+        .unwrap();
+    Response::from(response)
+}
+
+/// A random version 4 UUID.
+fn uuid_v4() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// The urls to request, the arguments followed by the lines of the --url-file, with their
+/// globs expanded. A line of the file may name the file to save its response in after the url.
+fn load_targets(matches: &ArgMatches) -> Vec<(glob::Expanded, Option<String>)> {
+    let mut lines: Vec<(String, Option<String>, i64)> = matches
+        .values_of("uri")
+        .unwrap_or_default()
+        .map(|u| (u.to_string(), None, 0))
+        .collect();
+    if let Some(path) = matches.value_of("url-file") {
+        let content = if path == "-" { io::read_to_string(io::stdin()) } else { fs::read_to_string(path) }
+            .expect("Could not read the url file");
+        for line in content.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let (line, priority) = match line.rsplit_once(char::is_whitespace) {
+                Some((rest, last)) if last.starts_with("priority=") => {
+                    let priority = last["priority=".len()..]
+                        .parse()
+                        .unwrap_or_else(|_| panic!("Invalid {} in the url file, expected a whole number", last));
+                    (rest.trim_end(), priority)
+                }
+                _ => (line, 0),
+            };
+            match line.split_once(char::is_whitespace) {
+                Some((url, out)) => lines.push((url.to_string(), Some(out.trim().to_string()), priority)),
+                None => lines.push((line.to_string(), None, priority)),
+            }
+        }
+    }
+    // Higher priorities are fetched first, equal ones in the order they were given.
+    lines.sort_by_key(|(_, _, priority)| Reverse(*priority));
+    let mut targets = Vec::new();
+    for (url, out, _) in lines {
+        for expanded in glob::expand(&url).unwrap_or_else(|err| panic!("{}", err)) {
+            targets.push((expanded, out.clone()));
+        }
+    }
+    if targets.is_empty() {
+        panic!("No urls to request");
+    }
+    targets
+}
+
+/// The --mirror urls followed by the lines of the --mirror-file.
+fn load_mirrors(matches: &ArgMatches) -> Vec<String> {
+    let mut mirrors: Vec<String> = matches
+        .values_of("mirror")
+        .unwrap_or_default()
+        .map(|m| m.to_string())
+        .collect();
+    if let Some(path) = matches.value_of("mirror-file") {
+        let content = fs::read_to_string(path).expect("Could not read the mirror file");
+        mirrors.extend(
+            content
+                .lines()
+                .map(|l| l.trim())
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(|l| l.to_string()),
+        );
+    }
+    mirrors
+}
 
-    let req_builder = match method {
-        "GET" => client.get(uri),
+/// Why the next mirror should be tried, `None` when the attempt counts.
+/// The -X method in upper case, without it POST when there is a body to send and GET otherwise.
+fn request_method(matches: &ArgMatches) -> String {
+    match matches.value_of("method") {
+        Some(method) => method.to_uppercase(),
+        None if matches.is_present("data") || matches.is_present("form") => "POST".to_string(),
+        None => "GET".to_string(),
+    }
+}
+
+fn fallback_reason(outcome: &Outcome) -> Option<String> {
+    if let Some(err) = &outcome.error {
+        return Some(err.to_string());
+    }
+    if outcome.exit_code == EXIT_CHECKSUM_MISMATCH {
+        return Some("checksum mismatch".to_string());
+    }
+    if outcome.exit_code == EXIT_TRANSFER_FAILED && outcome.status.is_none() {
+        return outcome.case.errors.first().cloned();
+    }
+    outcome
+        .status
+        .filter(|s| s.is_server_error())
+        .map(|s| format!("HTTP status {}", s.as_u16()))
+}
+
+/// How much of a binary body is shown on a terminal without --hexdump.
+const HEXDUMP_PREVIEW: usize = 512;
+
+fn print_hexdump(body: &[u8], full: bool) {
+    if full || body.len() <= HEXDUMP_PREVIEW {
+        print!("{}", trace::hex_dump(body));
+        return;
+    }
+    print!("{}", trace::hex_dump(&body[..HEXDUMP_PREVIEW]));
+    println!(
+        "{}",
+        format!(
+            "... {} more bytes of binary data, use --hexdump to see all or -o to save it",
+            body.len() - HEXDUMP_PREVIEW
+        )
+        .dimmed()
+    );
+}
+
+/// Builds, sends and prints one request to `uri` with the placeholders filled from `vars`.
+/// With `fallback` set a 5xx response is not printed, a mirror is tried instead.
+/// `out` is the file to save the body in, its placeholders not yet filled.
+async fn perform(
+    matches: &ArgMatches,
+    client: &Client,
+    vars: &Vars,
+    uri: &str,
+    fallback: bool,
+    idempotency_key: Option<&str>,
+    out: Option<&str>,
+) -> Outcome {
+    let mut uri = match parse_url(&template::render(uri, vars)) {
+        Ok(uri) => uri,
+        Err(message) => {
+            eprintln!("{}", message.red());
+            let mut case = TestCase::new(uri);
+            case.errors.push(message);
+            return Outcome { case, status: None, exit_code: EXIT_URL_MALFORMED, error: None, body: None, bytes: 0 };
+        }
+    };
+    let profile = uri.host_str().and_then(|host| profile::for_host(matches, host));
+    if let (Some(host), Some(_), true) = (uri.host_str(), &profile, matches.is_present("verbose")) {
+        println!("* Using the profile for {}", host);
+    }
+    // The url is sent to the --sni-name host, with that name pinned to the address of the url.
+    let sni = matches.value_of("sni-name").filter(|_| is_http(&uri));
+    // Each host gets its own happy eyeballs race.
+    let race = matches.is_present("happy-eyeballs-timeout-ms") && is_http(&uri);
+    let own_client;
+    let client = if sni.is_some() || race || profile.as_ref().is_some_and(Profile::needs_client) {
+        own_client = match build_client(matches, Some(&uri), sni, profile.as_ref()).await {
+            Ok(client) => client,
+            Err(message) => {
+                // With a mirror left the message shows up as the reason to try it.
+                if !fallback {
+                    eprintln!("{}", message.red());
+                }
+                let mut case = TestCase::new(&format!("{} {}", request_method(matches), uri));
+                case.errors.push(message);
+                return Outcome { case, status: None, exit_code: EXIT_TRANSFER_FAILED, error: None, body: None, bytes: 0 };
+            }
+        };
+        if let Some(sni) = sni {
+            uri.set_host(Some(sni)).unwrap_or_else(|err| panic!("Invalid --sni-name {}: {}", sni, err));
+        }
+        &own_client
+    } else {
+        client
+    };
+    if matches.is_present("upload-file") && !matches!(uri.scheme(), "sftp" | "scp") {
+        panic!("--upload-file is only supported for sftp:// and scp:// urls");
+    }
+
+    let method = request_method(matches);
+
+    let req_builder = match method.as_str() {
+        "GET" => client.get(uri.clone()),
         "POST" | "PUT" | "PATCH" => {
-            let b = match method {
-                "PUT" => client.put(uri),
-                "PATCH" => client.patch(uri),
-                _ => client.post(uri),
+            let b = match method.as_str() {
+                "PUT" => client.put(uri.clone()),
+                "PATCH" => client.patch(uri.clone()),
+                _ => client.post(uri.clone()),
             };
             if matches.is_present("form") {
-                b.form(&parse_fields(&matches))
+                b.form(&parse_fields(matches, vars))
             } else if matches.is_present("data"){
-                b.body(parse_data(&matches))
+                b.body(parse_data(matches, vars))
             } else {
                 b
             }
         }
-        "HEAD" => client.head(uri),
-        "DELETE" => client.delete(uri),
+        "HEAD" => client.head(uri.clone()),
+        "DELETE" => client.delete(uri.clone()),
         _ => panic!("Invalid method")
     };
 
-    let req = req_builder
-        .headers(parse_headers(&matches))
-        .build()
-        .unwrap();
+    // reqwest refuses to build requests for other schemes, those are read by file_response and ssh_response.
+    let mut req = if !is_http(&uri) {
+        Request::new(Method::GET, uri.clone())
+    } else {
+        req_builder
+            .headers(parse_headers(matches, vars))
+            .build()
+            .unwrap()
+    };
+
+    let resume_from = matches
+        .value_of("continue-at")
+        .map(|at| match at {
+            "-" => {
+                let out = out.expect("--continue-at - needs -o to know where to continue");
+                fs::metadata(template::render(out, vars)).map(|m| m.len()).unwrap_or(0)
+            }
+            offset => offset.parse().expect("--continue-at must be a byte offset or -"),
+        })
+        .filter(|offset| *offset > 0);
+    if let (Some(profile), true) = (&profile, is_http(&uri)) {
+        // -H wins over the profile, which may repeat a header itself.
+        let given: Vec<HeaderName> = req.headers().keys().cloned().collect();
+        for line in &profile.headers {
+            let (name, value) = parse::header(&template::render(line, vars)).unwrap_or_else(|err| panic!("{}", err));
+            if !given.contains(&name) {
+                req.headers_mut().append(name, value);
+            }
+        }
+        if let Some(timeout) = profile.timeout {
+            *req.timeout_mut() = Some(timeout);
+        }
+    }
+
+    if let Some(offset) = resume_from {
+        req.headers_mut().insert(RANGE, HeaderValue::from_str(&format!("bytes={}-", offset)).unwrap());
+    }
+
+    if let Some(encoding) = matches.value_of("compress-request") {
+        if let Some(compressed) = req.body().and_then(|b| b.as_bytes()).map(|b| compress::compress(encoding, b)) {
+            *req.body_mut() = Some(compressed.into());
+            req.headers_mut().insert(CONTENT_ENCODING, HeaderValue::from_str(encoding).unwrap());
+        }
+    }
+
+    if let Some(key) = idempotency_key {
+        if !req.headers().contains_key("idempotency-key") {
+            let key = HeaderValue::from_str(key).expect("Invalid --idempotency-key");
+            req.headers_mut().insert("idempotency-key", key);
+        }
+    }
+
+    if let Some(spec) = matches.value_of("hmac-sign") {
+        let sign = HmacSign::parse(spec, vars).unwrap_or_else(|err| panic!("{}", err));
+        sign.sign(&mut req, vars).unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    let span = if matches.is_present("otel") {
+        let span = Span::start(req.headers());
+        req.headers_mut().insert("traceparent", span.traceparent());
+        Some(span)
+    } else {
+        None
+    };
+
+    let redactor = Redactor::from_matches(matches);
+
+    let mut tracer = Tracer::from_matches(matches);
+
+    let raw_request = matches
+        .value_of("raw-request")
+        .map(|path| fs::read(path).expect("Could not read the raw request file"));
+
+    if matches.is_present("dry-run") {
+        print_idn(req.url());
+        match &raw_request {
+            Some(raw) => print_raw_request(raw),
+            None => {
+                print_req(&req, &redactor);
+                if let Some(body) = req.body().and_then(|b| b.as_bytes()) {
+                    print_body(body);
+                }
+            }
+        }
+        return Outcome {
+            case: TestCase::new(&format!("{} {}", req.method(), req.url())),
+            status: None,
+            exit_code: 0,
+            error: None,
+            body: None,
+            bytes: 0,
+        };
+    }
 
     if matches.is_present("verbose") {
-        print_req(&req);
+        print_idn(req.url());
+        match &raw_request {
+            Some(raw) => print_raw_request(raw),
+            None => print_req(&req, &redactor),
+        }
     }
 
-    let response = client.execute(req).await?;
+    if let Some(tracer) = tracer.as_mut() {
+        tracer.info(&format!("Sending request to {}", req.url()));
+        tracer.send_header(&req, &redactor);
+        if let Some(body) = req.body().and_then(|b| b.as_bytes()) {
+            tracer.send_data(body);
+        }
+    }
+
+    let json_output = matches.value_of("output-format") == Some("json");
+    let request_info = if json_output { Some(RequestInfo::new(&req, &redactor)) } else { None };
+    let sent = if matches.is_present("report") { req.try_clone() } else { None };
+
+    let tls = if matches.is_present("cert-info") && req.url().scheme() == "https" {
+        match certinfo::inspect(req.url()).await {
+            Ok(info) if json_output => Some(info.to_json()),
+            Ok(info) => {
+                info.print();
+                None
+            }
+            Err(err) => {
+                eprintln!("{}", err.red());
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut transaction = Transaction::start(&req);
+
+    let parallel_chunks = matches
+        .value_of("parallel-chunks")
+        .map(|n| n.parse::<usize>().expect("--parallel-chunks must be a number"));
+    // Kept to repeat the request with a Range header once the server turns out to accept ranges.
+    let chunk_template = parallel_chunks
+        .filter(|_| req.method() == Method::GET && is_http(req.url()))
+        .and_then(|_| req.try_clone());
+
+    let response_url = req.url().clone();
+    let mut trailers = HeaderMap::new();
+    let local = match req.url().scheme() {
+        "file" => Some(file_response(req.url()).map_err(|err| (err, EXIT_FILE_UNREADABLE))),
+        "sftp" | "scp" => Some(ssh_response(matches, req.url()).await.map_err(|err| (err, EXIT_TRANSFER_FAILED))),
+        _ if raw_request.is_some() => Some(
+            http1::send_raw(req.url(), raw_request.clone().unwrap())
+                .await
+                .map(|(response, received)| {
+                    trailers = received;
+                    response
+                })
+                .map_err(|err| (err, EXIT_TRANSFER_FAILED)),
+        ),
+        _ if matches.is_present("trailer") => Some(
+            http1::send_with_trailers(&req, parse_trailers(matches, vars))
+                .await
+                .map(|(response, received)| {
+                    trailers = received;
+                    response
+                })
+                .map_err(|err| (err, EXIT_TRANSFER_FAILED)),
+        ),
+        _ => None,
+    };
+    let result = match local {
+        Some(Ok(response)) => Ok(response),
+        Some(Err((message, exit_code))) => {
+            transaction.failed(&message);
+            eprintln!("{}", message.red());
+            return Outcome {
+                case: finish_transaction(matches, &transaction, span.as_ref(), None).await,
+                status: None,
+                exit_code,
+                error: None,
+                body: None,
+                bytes: 0,
+            };
+        }
+        None => redirect::send(client, req, &redirect::Policy::from_matches(matches)).await,
+    };
+    let response = match result {
+        Ok(response) => response,
+        Err(err) => {
+            transaction.failed(&err);
+            if let Some(request_info) = &request_info {
+                println!("{}", document::render(request_info, None, &transaction, tls.as_ref(), &redactor));
+            }
+            return Outcome {
+                case: finish_transaction(matches, &transaction, span.as_ref(), None).await,
+                status: None,
+                exit_code: EXIT_TRANSFER_FAILED,
+                error: Some(err),
+                body: None,
+                bytes: 0,
+            };
+        }
+    };
+    let status = response.status();
+    let version = format!("{:?}", response.version());
+    transaction.headers_received(status.as_u16());
 
     if matches.is_present("verbose") {
-        print_res(&response);
+        print_res(&response, &redactor);
     }
 
+    if let Some(tracer) = tracer.as_mut() {
+        tracer.recv_header(&response, &redactor);
+    }
 
-    let text = response.text().await?;
 
-    if matches.is_present("out") {
-        if let Some(path_str) = matches.value_of("out") {
-            println!("Saving...");
-            save_in_file(PathBuf::from(path_str), text).await.expect("Could not save the file");
-            println!("Saved response text in {}", path_str)
+    if fallback && status.is_server_error() {
+        return Outcome {
+            case: finish_transaction(matches, &transaction, span.as_ref(), None).await,
+            status: Some(status),
+            exit_code: 0,
+            error: None,
+            body: None,
+            bytes: 0,
+        };
+    }
+
+    if resume_from.is_some() && status == StatusCode::RANGE_NOT_SATISFIABLE {
+        println!("Nothing left to download, {} is complete", out.map(|o| template::render(o, vars)).unwrap_or_default());
+        return Outcome {
+            case: finish_transaction(matches, &transaction, span.as_ref(), None).await,
+            status: Some(status),
+            exit_code: 0,
+            error: None,
+            body: None,
+            bytes: 0,
+        };
+    }
+    if resume_from.is_some() && status != StatusCode::PARTIAL_CONTENT {
+        eprintln!("{}", format!("The server answered {} to the range request, cannot resume", status).red());
+        return Outcome {
+            case: finish_transaction(matches, &transaction, span.as_ref(), None).await,
+            status: Some(status),
+            exit_code: EXIT_RANGE_ERROR,
+            error: None,
+            body: None,
+            bytes: 0,
+        };
+    }
+
+    let frontmatter = if matches.is_present("with-frontmatter") {
+        let url = if is_http(&response_url) { response.url() } else { &response_url };
+        render_frontmatter(url.as_str(), &response.status(), response.headers())
+    } else {
+        String::new()
+    };
+
+    let max_filesize = matches.value_of("max-filesize").map(parse_size);
+    if let (Some(max), Some(len)) = (max_filesize, response.content_length()) {
+        if len > max {
+            return abort_max_filesize(matches, &mut transaction, span.as_ref(), status, max).await;
+        }
+    }
+
+    let headers = response.headers().clone();
+    let mut response = response;
+    let mut body = Vec::new();
+    let mut checksums = Checksum::from_matches(matches);
+    // Resolved once, so that an interrupted transfer is kept where the complete one would go.
+    let out_path = out.map(|o| {
+        let path = template::render(o, vars);
+        if matches.is_present("no-clobber") && !matches.is_present("append") {
+            glob::unclobbered(Path::new(&path)).display().to_string()
+        } else {
+            path
+        }
+    });
+    let save_mode = SaveMode::from_matches(matches, resume_from.is_some());
+    let ranges = chunk_template
+        .as_ref()
+        .zip(parallel_chunks)
+        .and_then(|(template, n)| chunks::probe(&response, n).map(|len| (template, n, len)));
+    if chunk_template.is_some() && ranges.is_none() && matches.is_present("verbose") {
+        println!("* The server does not accept ranges, downloading in one piece");
+    }
+    if let Some((template, n, len)) = ranges {
+        if matches.is_present("verbose") {
+            println!("* Downloading {} bytes in {} ranges", len, n);
+        }
+        // The ranges are asked from where the redirects ended up.
+        let mut template = template.try_clone().unwrap();
+        *template.url_mut() = response.url().clone();
+        drop(response);
+        let validator = chunks::validator(&headers);
+        match chunks::download(client, &template, validator, len, n, matches.is_present("verbose")).await {
+            Ok(bytes) => {
+                for checksum in checksums.iter_mut() {
+                    checksum.update(&bytes);
+                }
+                body = bytes;
+            }
+            Err(message) => {
+                transaction.failed(&message);
+                eprintln!("{}", message.red());
+                return Outcome {
+                    case: finish_transaction(matches, &transaction, span.as_ref(), None).await,
+                    status: Some(status),
+                    exit_code: EXIT_TRANSFER_FAILED,
+                    error: None,
+                    body: None,
+                    bytes: 0,
+                };
+            }
         }
     } else {
-        println!("{}", text.trim_end());
+        let _transfer = interrupt::Transfer::start();
+        loop {
+            let chunk = tokio::select! {
+                chunk = response.chunk() => chunk,
+                _ = interrupt::interrupted() => {
+                    save_interrupted(out_path.as_deref(), &body, &save_mode);
+                    process::exit(interrupt::EXIT_INTERRUPTED);
+                }
+            };
+            match chunk {
+                Ok(Some(chunk)) => {
+                    body.extend_from_slice(&chunk);
+                    for checksum in checksums.iter_mut() {
+                        checksum.update(&chunk);
+                    }
+                    if let Some(max) = max_filesize.filter(|max| body.len() as u64 > *max) {
+                        transaction.body_received(body.len());
+                        return abort_max_filesize(matches, &mut transaction, span.as_ref(), status, max).await;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    transaction.failed(&err);
+                    return Outcome {
+                        case: finish_transaction(matches, &transaction, span.as_ref(), None).await,
+                        status: Some(status),
+                        exit_code: EXIT_TRANSFER_FAILED,
+                        error: Some(err),
+                        body: None,
+                        bytes: 0,
+                    };
+                }
+            }
+        }
     }
+    transaction.body_received(body.len());
 
-    Ok(())
-}
+    if let Some(tracer) = tracer.as_mut() {
+        tracer.recv_data(&body);
+    }
+
+    let mismatches: Vec<String> = checksums.into_iter().filter_map(Checksum::verify).collect();
+    if !mismatches.is_empty() {
+        for mismatch in &mismatches {
+            eprintln!("{}", mismatch.red());
+        }
+        eprintln!("{}", "The body was not saved or printed.".red());
+        return Outcome {
+            case: finish_transaction(matches, &transaction, span.as_ref(), Some(&mismatches)).await,
+            status: Some(status),
+            exit_code: EXIT_CHECKSUM_MISMATCH,
+            error: None,
+            body: None,
+            bytes: 0,
+        };
+    }
+    let text = charset::decode(&headers, &body, charset::forced(matches));
+
+    let failures = if expect::is_requested(matches) {
+        Some(expect::check(matches, status, &headers, &text))
+    } else {
+        None
+    };
+    let mut case = finish_transaction(matches, &transaction, span.as_ref(), failures.as_deref()).await;
+    let too_slow = over_budget(matches, &transaction);
+    case.failures.extend(too_slow.iter().cloned());
+    if let Some(sent) = &sent {
+        case.attach_request(sent, &redactor, attach_limit(matches));
+    }
+    case.attach(status.as_u16(), &headers, &text, &redactor, attach_limit(matches));
+    let kept_body = if matches.is_present("fan-out") { Some(text.clone()) } else { None };
+    let bytes = body.len() as u64;
+
+    let mut saved_to = None;
+    if let Some(path_str) = out_path {
+        if !json_output {
+            println!("Saving...");
+        }
+        // The file gets the bytes the checksums covered, the decoded text is only for display.
+        // A commented YAML block would corrupt a binary file, those are saved as they came.
+        let mut data = if document::is_text(&headers, &body) { frontmatter.into_bytes() } else { Vec::new() };
+        data.extend_from_slice(&body);
+        match &save_mode {
+            SaveMode::Resume => {
+                let mut file = OpenOptions::new().append(true).open(&path_str).expect("Could not open the file to resume");
+                file.write_all(&body).expect("Could not save the file");
+                if !json_output {
+                    println!("Appended {} bytes to {} after byte {}", body.len(), path_str, resume_from.unwrap_or_default())
+                }
+            }
+            SaveMode::Append(separator) => {
+                append_to_file(PathBuf::from(&path_str), &data, separator).await.expect("Could not save the file");
+                if !json_output {
+                    println!("Appended response text to {}", path_str)
+                }
+            }
+            SaveMode::Create => {
+                save_in_file(PathBuf::from(&path_str), &data).await.expect("Could not save the file");
+                if matches.is_present("remote-time") {
+                    set_remote_time(Path::new(&path_str), &headers);
+                }
+                if !json_output {
+                    println!("Saved response text in {}", path_str)
+                }
+            }
+        }
+        saved_to = Some(path_str);
+    } else if !json_output
+        && (matches.is_present("hexdump") || (io::stdout().is_terminal() && !document::is_text(&headers, &body)))
+    {
+        print_hexdump(&body, matches.is_present("hexdump"));
+    } else if !json_output
+        && !io::stdout().is_terminal()
+        && !["grep", "head-lines", "tail-lines"].iter().any(|name| matches.is_present(name))
+    {
+        // Pipes and redirects get the body as it arrived.
+        let mut stdout = io::stdout();
+        stdout.write_all(&body).and_then(|_| stdout.flush()).expect("Could not write the body");
+    } else if !json_output {
+        let mut body = text.trim_end().to_string();
+        if !matches.is_present("raw") && io::stdout().is_terminal() && highlight::detect(&headers) == Some("xml") {
+            if let Some(pretty) = pretty::xml(&body) {
+                body = pretty;
+            }
+        }
+        if let Some(pattern) = matches.value_of("grep") {
+            let pattern = Regex::new(pattern).expect("Invalid --grep pattern");
+            let context = matches
+                .value_of("context")
+                .map(|c| c.parse::<usize>().expect("--context must be a number"))
+                .unwrap_or(0);
+            body = filter::grep(&body, &pattern, context);
+        }
+        if let Some(count) = matches.value_of("head-lines") {
+            body = filter::head_lines(&body, count.parse().expect("--head-lines must be a number"));
+        } else if let Some(count) = matches.value_of("tail-lines") {
+            body = filter::tail_lines(&body, count.parse().expect("--tail-lines must be a number"));
+        }
+        if !matches.is_present("grep") {
+            if let Some(language) = highlight::language(&headers, matches.value_of("highlight")) {
+                body = highlight::highlight(&body, language);
+            }
+        }
+        if !body.is_empty() {
+            println!("{}", body);
+        }
+    }
 
+    if matches.is_present("verbose") && !trailers.is_empty() {
+        for (name, value) in &trailers {
+            println!("< {}: {}", name, redactor.display(name.as_str(), &String::from_utf8_lossy(value.as_bytes())));
+        }
+        println!("<");
+    }
 
+    if let Some(request_info) = &request_info {
+        let body = match saved_to {
+            Some(path) => document::Body::File(path),
+            None if document::is_text(&headers, &body) => document::Body::Text(text),
+            None => document::Body::Binary(body),
+        };
+        let response = document::ResponseInfo {
+            status: status.as_u16(),
+            version,
+            headers: &headers,
+            body,
+        };
+        println!("{}", document::render(request_info, Some(response), &transaction, tls.as_ref(), &redactor));
+    }
 
+    let mut exit_code = 0;
+    if let Some(failures) = failures.filter(|f| !f.is_empty()) {
+        for failure in failures {
+            eprintln!("{}", failure.red());
+        }
+        exit_code = EXIT_EXPECTATION_FAILED;
+    }
+    if !too_slow.is_empty() {
+        for problem in &too_slow {
+            eprintln!("{}", problem.red());
+        }
+        if exit_code == 0 {
+            exit_code = EXIT_TOO_SLOW;
+        }
+    }
+
+    Outcome {
+        case,
+        status: Some(status),
+        exit_code,
+        error: None,
+        body: kept_body,
+        bytes,
+    }
+}
@@ -0,0 +1,145 @@
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde_json::{json, Value};
+use crate::txlog::Transaction;
+
+/// W3C trace context of the client span that wraps the request.
+pub struct Span {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    sampled: bool,
+}
+
+impl Span {
+    /// Continues the trace from a `traceparent` header given with -H or the
+    /// `TRACEPARENT` environment variable, otherwise starts a new one.
+    pub fn start(headers: &HeaderMap) -> Span {
+        let parent = headers
+            .get("traceparent")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+            .or_else(|| env::var("TRACEPARENT").ok())
+            .and_then(|v| parse_traceparent(&v));
+        let span_id = random_hex(8);
+        match parent {
+            Some((trace_id, parent_span_id, sampled)) => Span {
+                trace_id,
+                span_id,
+                parent_span_id: Some(parent_span_id),
+                sampled,
+            },
+            None => Span {
+                trace_id: random_hex(16),
+                span_id,
+                parent_span_id: None,
+                sampled: true,
+            },
+        }
+    }
+
+    pub fn traceparent(&self) -> HeaderValue {
+        let flags = if self.sampled { "01" } else { "00" };
+        HeaderValue::from_str(&format!("00-{}-{}-{}", self.trace_id, self.span_id, flags))
+            .expect("Invalid traceparent")
+    }
+
+    /// Sends the span to the OTLP/HTTP endpoint configured through the standard
+    /// `OTEL_EXPORTER_OTLP_*` variables. Does nothing when none is set.
+    pub async fn export(&self, transaction: &Transaction) {
+        if !self.sampled {
+            return;
+        }
+        let endpoint = match env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+            Ok(endpoint) => endpoint,
+            Err(_) => match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+                Ok(base) => format!("{}/v1/traces", base.trim_end_matches('/')),
+                Err(_) => return,
+            },
+        };
+        let mut request = reqwest::Client::new().post(&endpoint).json(&self.to_otlp(transaction));
+        if let Ok(headers) = env::var("OTEL_EXPORTER_OTLP_HEADERS") {
+            for pair in headers.split(',') {
+                if let Some((k, v)) = pair.split_once('=') {
+                    request = request.header(k.trim(), v.trim());
+                }
+            }
+        }
+        match request.send().await {
+            Ok(res) if !res.status().is_success() => {
+                eprintln!("Could not export the span to {}: {}", endpoint, res.status())
+            }
+            Err(err) => eprintln!("Could not export the span to {}: {}", endpoint, err),
+            _ => {}
+        }
+    }
+
+    fn to_otlp(&self, transaction: &Transaction) -> Value {
+        let mut attributes = vec![
+            string_attribute("http.request.method", transaction.method()),
+            string_attribute("url.full", transaction.url()),
+        ];
+        if let Some(status) = transaction.status() {
+            attributes.push(json!({
+                "key": "http.response.status_code",
+                "value": {"intValue": status.to_string()}
+            }));
+        }
+        let failed = transaction.error().is_some() || transaction.status().is_some_and(|s| s >= 400);
+        let mut status = json!({"code": if failed { 2 } else { 0 }});
+        if let Some(error) = transaction.error() {
+            status["message"] = json!(error);
+            attributes.push(string_attribute("error.type", error));
+        }
+        let service = env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string());
+        json!({
+            "resourceSpans": [{
+                "resource": {"attributes": [string_attribute("service.name", &service)]},
+                "scopeSpans": [{
+                    "scope": {"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")},
+                    "spans": [{
+                        "traceId": self.trace_id,
+                        "spanId": self.span_id,
+                        "parentSpanId": self.parent_span_id.clone().unwrap_or_default(),
+                        "name": transaction.method(),
+                        "kind": 3,
+                        "startTimeUnixNano": unix_nanos(transaction.started_at()).to_string(),
+                        "endTimeUnixNano": unix_nanos(SystemTime::now()).to_string(),
+                        "attributes": attributes,
+                        "status": status,
+                    }]
+                }]
+            }]
+        })
+    }
+}
+
+fn parse_traceparent(value: &str) -> Option<(String, String, bool)> {
+    let parts: Vec<&str> = value.trim().split('-').collect();
+    if parts.len() < 4 || parts[1].len() != 32 || parts[2].len() != 16 || parts[3].len() != 2 {
+        return None;
+    }
+    if !parts[1..4].iter().all(|p| p.chars().all(|c| c.is_ascii_hexdigit())) {
+        return None;
+    }
+    if parts[1].chars().all(|c| c == '0') || parts[2].chars().all(|c| c == '0') {
+        return None;
+    }
+    let flags = u8::from_str_radix(parts[3], 16).ok()?;
+    Some((parts[1].to_lowercase(), parts[2].to_lowercase(), flags & 1 == 1))
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..bytes).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({"key": key, "value": {"stringValue": value}})
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
@@ -0,0 +1,126 @@
+use reqwest::header::{HeaderName, HeaderValue};
+
+/// Splits a `Name: value` header line, the value is trimmed.
+pub fn header(line: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = line
+        .split_once(':')
+        .ok_or_else(|| format!("Unexpected header format {}, expected 'Name: value'", line))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("Invalid header name '{}'", name.trim()))?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|_| format!("Invalid value for header {}", name))?;
+    Ok((name, value))
+}
+
+/// Splits a `name=value` form field, leading whitespace of the value is dropped.
+pub fn form_field(field: &str) -> Result<(String, String), String> {
+    match field.split_once('=') {
+        Some((name, value)) if !value.contains('=') => Ok((name.to_string(), value.trim_start().to_string())),
+        _ => Err(format!("Unexpected form format {}, expected 'name=value'", field)),
+    }
+}
+
+/// Parses a byte count with an optional k, M or G suffix.
+pub fn size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (number, multiplier) = match size.chars().last() {
+        Some('k') | Some('K') => (&size[..size.len() - 1], 1024),
+        Some('m') | Some('M') => (&size[..size.len() - 1], 1024 * 1024),
+        Some('g') | Some('G') => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (size, 1),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("Invalid size {}", size))
+}
+
+/// Parses a percentage from 0 to 100, the `%` sign is optional.
+pub fn percent(text: &str) -> Result<f64, String> {
+    let number = text.trim();
+    let number = number.strip_suffix('%').unwrap_or(number);
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|p| (0.0..=100.0).contains(p))
+        .ok_or_else(|| format!("Invalid percentage {}, expected 0 to 100", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn header_splits_on_the_first_colon() {
+        let (name, value) = header("X-Time: 12:30").unwrap();
+        assert_eq!(name, "x-time");
+        assert_eq!(value, "12:30");
+    }
+
+    #[test]
+    fn header_rejects_bad_names_and_values() {
+        assert!(header("no colon").is_err());
+        assert!(header("Bad Name: x").is_err());
+        assert!(header(": x").is_err());
+        assert!(header("X-Bad: a\nb").is_err());
+    }
+
+    #[test]
+    fn form_field_needs_exactly_one_equals_sign() {
+        assert_eq!(form_field("a= b").unwrap(), ("a".to_string(), "b".to_string()));
+        assert!(form_field("a").is_err());
+        assert!(form_field("a=b=c").is_err());
+    }
+
+    #[test]
+    fn size_handles_suffixes_and_overflow() {
+        assert_eq!(size("10").unwrap(), 10);
+        assert_eq!(size("2k").unwrap(), 2048);
+        assert_eq!(size("1G").unwrap(), 1024 * 1024 * 1024);
+        assert!(size("").is_err());
+        assert!(size("k").is_err());
+        assert!(size("-1").is_err());
+        assert!(size("18446744073709551615G").is_err());
+    }
+
+    #[test]
+    fn percent_takes_an_optional_sign() {
+        assert_eq!(percent("95%").unwrap(), 95.0);
+        assert_eq!(percent(" 99.5 ").unwrap(), 99.5);
+        assert!(percent("101%").is_err());
+        assert!(percent("NaN").is_err());
+        assert!(percent("%").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn header_never_panics(line in any::<String>()) {
+            let _ = header(&line);
+        }
+
+        #[test]
+        fn header_round_trips(name in "[A-Za-z0-9-]{1,20}", value in "[ -~]{0,40}") {
+            let (parsed_name, parsed_value) = header(&format!("{}: {}", name, value)).unwrap();
+            prop_assert_eq!(parsed_name.as_str(), name.to_lowercase());
+            prop_assert_eq!(parsed_value.to_str().unwrap(), value.trim());
+        }
+
+        #[test]
+        fn form_field_never_panics(field in any::<String>()) {
+            let _ = form_field(&field);
+        }
+
+        #[test]
+        fn percent_never_panics(text in any::<String>()) {
+            let _ = percent(&text);
+        }
+
+        #[test]
+        fn size_never_panics(text in any::<String>()) {
+            let _ = size(&text);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+enum Token<'a> {
+    Open(&'a str),
+    Close(&'a str),
+    /// Self-closing elements, comments, declarations and CDATA sections.
+    Empty(&'a str),
+    Text(&'a str),
+}
+
+/// Re-indents an XML document, elements holding only text stay on one line.
+/// Returns `None` when the text does not look like well-formed XML.
+pub fn xml(text: &str) -> Option<String> {
+    let tokens = tokenize(text)?;
+    let mut out = String::with_capacity(text.len() * 2);
+    // Names of the open elements, a close tag has to match the innermost one.
+    let mut open_names = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let indent = "  ".repeat(open_names.len());
+        match (&tokens[i], tokens.get(i + 1), tokens.get(i + 2)) {
+            (Token::Open(open), Some(Token::Text(text)), Some(Token::Close(close))) if name(open) == name(close) => {
+                out.push_str(&format!("{}{}{}{}\n", indent, open, text, close));
+                i += 3;
+                continue;
+            }
+            (Token::Open(open), Some(Token::Close(close)), _) if name(open) == name(close) => {
+                out.push_str(&format!("{}{}{}\n", indent, open, close));
+                i += 2;
+                continue;
+            }
+            (Token::Open(open), _, _) => {
+                out.push_str(&format!("{}{}\n", indent, open));
+                open_names.push(name(open));
+            }
+            (Token::Close(close), _, _) => {
+                if open_names.pop()? != name(close) {
+                    return None;
+                }
+                out.push_str(&format!("{}{}\n", "  ".repeat(open_names.len()), close));
+            }
+            (Token::Empty(tag), _, _) => out.push_str(&format!("{}{}\n", indent, tag)),
+            (Token::Text(text), _, _) => out.push_str(&format!("{}{}\n", indent, text)),
+        }
+        i += 1;
+    }
+    if !open_names.is_empty() {
+        return None;
+    }
+    Some(out.trim_end().to_string())
+}
+
+/// The element name of an open or close tag.
+fn name(tag: &str) -> &str {
+    let tag = tag.trim_start_matches("</").trim_start_matches('<');
+    let end = tag.find(|c: char| c.is_whitespace() || c == '>' || c == '/').unwrap_or(tag.len());
+    &tag[..end]
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim();
+    if !rest.starts_with('<') {
+        return None;
+    }
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let content = rest[..end].trim();
+            if !content.is_empty() {
+                tokens.push(Token::Text(content));
+            }
+            rest = &rest[end..];
+            continue;
+        }
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->")? + 3
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>")? + 3
+        } else if rest.starts_with("<?") {
+            rest.find("?>")? + 2
+        } else {
+            tag_end(rest)?
+        };
+        let tag = &rest[..end];
+        tokens.push(if tag.starts_with("</") {
+            Token::Close(tag)
+        } else if tag.starts_with("<!") || tag.starts_with("<?") || tag.ends_with("/>") {
+            Token::Empty(tag)
+        } else {
+            Token::Open(tag)
+        });
+        rest = &rest[end..];
+    }
+    Some(tokens)
+}
+
+/// Index after the `>` closing the tag at the start of `text`, skipping quoted attribute values.
+fn tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indents_nested_elements() {
+        assert_eq!(
+            xml("<a><b><c>1</c></b><d x=\"y>z\"></d></a>").unwrap(),
+            "<a>\n  <b>\n    <c>1</c>\n  </b>\n  <d x=\"y>z\"></d>\n</a>"
+        );
+    }
+
+    #[test]
+    fn keeps_text_only_elements_on_one_line() {
+        assert_eq!(xml("<name>\n  Ada Lovelace\n</name>").unwrap(), "<name>Ada Lovelace</name>");
+        assert_eq!(xml("<p>a<b>b</b>c</p>").unwrap(), "<p>\n  a\n  <b>b</b>\n  c\n</p>");
+    }
+
+    #[test]
+    fn keeps_declarations_comments_cdata_and_empty_elements() {
+        let text = "<?xml version=\"1.0\"?><root><!-- a <b> --><![CDATA[<raw>]]><br/><img src=\"x\" /></root>";
+        assert_eq!(
+            xml(text).unwrap(),
+            "<?xml version=\"1.0\"?>\n<root>\n  <!-- a <b> -->\n  <![CDATA[<raw>]]>\n  <br/>\n  <img src=\"x\" />\n</root>"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(xml("not xml"), None);
+        assert_eq!(xml("<a><b></a>"), None);
+        assert_eq!(xml("<a></b>"), None);
+        assert_eq!(xml("<a>"), None);
+        assert_eq!(xml("</a>"), None);
+        assert_eq!(xml("<a><!-- open</a>"), None);
+        assert_eq!(xml("<a x=\"1></a>"), None);
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+use clap::ArgMatches;
+use crate::script::parse_duration;
+
+/// Settings a `[host "name"]` section of the config file applies to every request to that host.
+/// Flags given on the command line win over them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    pub proxy: Option<String>,
+    pub cert: Option<String>,
+    pub cert_type: Option<String>,
+    pub key: Option<String>,
+    pub pass: Option<String>,
+    /// `Name: value` lines like -H takes them.
+    pub headers: Vec<String>,
+    pub timeout: Option<Duration>,
+}
+
+impl Profile {
+    /// Whether the profile changes the client and not only the request.
+    pub fn needs_client(&self) -> bool {
+        self.proxy.is_some() || self.cert.is_some()
+    }
+}
+
+/// The profile for `host` from --config or the default config file, read once per run.
+/// `[host "*.example.com"]` covers the subdomains of example.com, an exact section wins.
+pub fn for_host(matches: &ArgMatches, host: &str) -> Option<Profile> {
+    static PROFILES: OnceLock<HashMap<String, Profile>> = OnceLock::new();
+    let profiles = PROFILES.get_or_init(|| load(matches));
+    profiles.get(host).or_else(|| {
+        profiles
+            .iter()
+            .filter_map(|(name, profile)| Some((name.strip_prefix("*.")?, profile)))
+            .filter(|(domain, _)| host.strip_suffix(domain).is_some_and(|rest| rest.ends_with('.')))
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(_, profile)| profile)
+    })
+    .cloned()
+}
+
+/// `$XDG_CONFIG_HOME/rust-curl/config`, or `~/.config/rust-curl/config`.
+fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("rust-curl").join("config"))
+}
+
+fn load(matches: &ArgMatches) -> HashMap<String, Profile> {
+    let (path, required) = match matches.value_of("config") {
+        Some(path) => (PathBuf::from(path), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return HashMap::new(),
+        },
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => parse(&text).unwrap_or_else(|err| panic!("{}: {}", path.display(), err)),
+        Err(_) if !required => HashMap::new(),
+        Err(err) => panic!("Could not read {}: {}", path.display(), err),
+    }
+}
+
+/// Reads `[host "name"]` sections of `key = value` lines. `#` and `;` start comments.
+pub fn parse(text: &str) -> Result<HashMap<String, Profile>, String> {
+    let mut profiles: HashMap<String, Profile> = HashMap::new();
+    let mut current: Option<String> = None;
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let host = section
+                .trim()
+                .strip_prefix("host")
+                .map(|rest| rest.trim())
+                .and_then(|rest| rest.strip_prefix('"')?.strip_suffix('"'))
+                .filter(|host| !host.is_empty())
+                .ok_or_else(|| format!("line {}: expected [host \"name\"], got [{}]", line_number, section))?;
+            let host = host.to_lowercase();
+            profiles.entry(host.clone()).or_default();
+            current = Some(host);
+            continue;
+        }
+        let host = current
+            .as_ref()
+            .ok_or_else(|| format!("line {}: settings must follow a [host \"name\"] section", line_number))?;
+        let (key, value) = line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim().to_string()))
+            .ok_or_else(|| format!("line {}: expected key = value", line_number))?;
+        let profile = profiles.get_mut(host).unwrap();
+        match key {
+            "proxy" => profile.proxy = Some(value),
+            "cert" => profile.cert = Some(value),
+            "cert-type" => profile.cert_type = Some(value),
+            "key" => profile.key = Some(value),
+            "pass" => profile.pass = Some(value),
+            "header" => profile.headers.push(value),
+            "timeout" => {
+                profile.timeout = Some(
+                    parse_duration(&value)
+                        .ok_or_else(|| format!("line {}: invalid timeout {}", line_number, value))?,
+                )
+            }
+            _ => return Err(format!("line {}: unknown setting {}", line_number, key)),
+        }
+    }
+    Ok(profiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_host_sections() {
+        let profiles = parse(
+            "# shared settings\n\
+             [host \"API.internal.example\"]\n\
+             proxy = http://proxy:3128\n\
+             header = X-Team: core\n\
+             header = Accept: application/json\n\
+             timeout = 1500ms\n\
+             \n\
+             [host \"*.example.com\"]\n\
+             cert = client.p12\n\
+             cert-type = P12\n",
+        )
+        .unwrap();
+        let api = &profiles["api.internal.example"];
+        assert_eq!(api.proxy.as_deref(), Some("http://proxy:3128"));
+        assert_eq!(api.headers, ["X-Team: core", "Accept: application/json"]);
+        assert_eq!(api.timeout, Some(Duration::from_millis(1500)));
+        assert!(profiles["*.example.com"].needs_client());
+    }
+
+    #[test]
+    fn rejects_unknown_lines() {
+        assert!(parse("proxy = x").unwrap_err().starts_with("line 1"));
+        assert!(parse("[host]").is_err());
+        assert!(parse("[host \"a\"]\nretries = 3").unwrap_err().contains("unknown setting retries"));
+        assert!(parse("[host \"a\"]\ntimeout = soon").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use clap::ArgMatches;
+use reqwest::header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HOST, LOCATION, PROXY_AUTHORIZATION};
+use reqwest::{Client, Method, Request, Response, StatusCode};
+
+/// Redirects followed before the last response is returned as it is.
+pub const MAX_REDIRECTS: usize = 10;
+
+/// How redirects are followed. The client itself follows none, so that the method,
+/// the body and the credentials can be carried over the way curl does it.
+pub struct Policy {
+    post301: bool,
+    post302: bool,
+    post303: bool,
+    trusted: bool,
+    verbose: bool,
+}
+
+impl Policy {
+    pub fn from_matches(matches: &ArgMatches) -> Policy {
+        Policy {
+            post301: matches.is_present("post301"),
+            post302: matches.is_present("post302"),
+            post303: matches.is_present("post303"),
+            trusted: matches.is_present("location-trusted"),
+            verbose: matches.is_present("verbose"),
+        }
+    }
+
+    /// Whether a redirect with `status` turns a `method` request into a GET without a body.
+    /// 301 and 302 only change POST, 303 changes everything but HEAD, 307 and 308 nothing.
+    fn switches_to_get(&self, status: StatusCode, method: &Method) -> bool {
+        match status {
+            StatusCode::MOVED_PERMANENTLY => method == Method::POST && !self.post301,
+            StatusCode::FOUND => method == Method::POST && !self.post302,
+            StatusCode::SEE_OTHER => method != Method::HEAD && !(method == Method::POST && self.post303),
+            _ => false,
+        }
+    }
+}
+
+/// Sends `req` and follows the redirects it gets, at most MAX_REDIRECTS of them.
+pub async fn send(client: &Client, req: Request, policy: &Policy) -> reqwest::Result<Response> {
+    let mut next = req.try_clone();
+    let mut response = client.execute(req).await?;
+    for _ in 0..MAX_REDIRECTS {
+        let status = response.status();
+        if !matches!(status.as_u16(), 301 | 302 | 303 | 307 | 308) {
+            break;
+        }
+        let location = response.headers().get(LOCATION).and_then(|l| l.to_str().ok());
+        let (mut req, url) = match (next, location.and_then(|l| response.url().join(l).ok())) {
+            (Some(req), Some(url)) => (req, url),
+            _ => break,
+        };
+        if policy.verbose {
+            println!("* {} redirects to {}", status, url);
+        }
+
+        if policy.switches_to_get(status, req.method()) {
+            *req.method_mut() = Method::GET;
+            *req.body_mut() = None;
+            for name in [CONTENT_TYPE, CONTENT_LENGTH, CONTENT_ENCODING] {
+                req.headers_mut().remove(name);
+            }
+        }
+        let same_host = url.host_str() == req.url().host_str() && url.port_or_known_default() == req.url().port_or_known_default();
+        // Like curl, a custom Host header is only meant for the host it was given for.
+        if !same_host && req.headers_mut().remove(HOST).is_some() && policy.verbose {
+            println!("* Not sending the Host header to {}", url.host_str().unwrap_or_default());
+        }
+        if !same_host && !policy.trusted {
+            for name in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION] {
+                if req.headers_mut().remove(&name).is_some() && policy.verbose {
+                    println!(
+                        "* Not sending {} to {}, pass --location-trusted to keep it",
+                        name,
+                        url.host_str().unwrap_or_default()
+                    );
+                }
+            }
+        }
+        *req.url_mut() = url;
+
+        next = req.try_clone();
+        response = client.execute(req).await?;
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(post301: bool, post302: bool, post303: bool) -> Policy {
+        Policy { post301, post302, post303, trusted: false, verbose: false }
+    }
+
+    #[test]
+    fn switches_post_to_get_like_curl() {
+        let default = policy(false, false, false);
+        assert!(default.switches_to_get(StatusCode::MOVED_PERMANENTLY, &Method::POST));
+        assert!(default.switches_to_get(StatusCode::FOUND, &Method::POST));
+        assert!(default.switches_to_get(StatusCode::SEE_OTHER, &Method::POST));
+        assert!(!default.switches_to_get(StatusCode::MOVED_PERMANENTLY, &Method::PUT));
+        assert!(!default.switches_to_get(StatusCode::FOUND, &Method::DELETE));
+        assert!(default.switches_to_get(StatusCode::SEE_OTHER, &Method::PUT));
+        assert!(!default.switches_to_get(StatusCode::SEE_OTHER, &Method::HEAD));
+        assert!(!default.switches_to_get(StatusCode::TEMPORARY_REDIRECT, &Method::POST));
+        assert!(!default.switches_to_get(StatusCode::PERMANENT_REDIRECT, &Method::POST));
+    }
+
+    #[test]
+    fn post30x_flags_keep_post() {
+        assert!(!policy(true, false, false).switches_to_get(StatusCode::MOVED_PERMANENTLY, &Method::POST));
+        assert!(!policy(false, true, false).switches_to_get(StatusCode::FOUND, &Method::POST));
+        assert!(!policy(false, false, true).switches_to_get(StatusCode::SEE_OTHER, &Method::POST));
+        // --post303 only keeps POST, other methods still become GET.
+        assert!(policy(false, false, true).switches_to_get(StatusCode::SEE_OTHER, &Method::PUT));
+    }
+}
@@ -0,0 +1,421 @@
+use std::fs;
+use std::io;
+use std::time::Duration;
+use clap::ArgMatches;
+use reqwest::header::HeaderMap;
+use reqwest::Request;
+use crate::txlog::Transaction;
+use crate::Redactor;
+
+/// Default for --attach-limit.
+pub const DEFAULT_ATTACH_LIMIT: usize = 64 * 1024;
+
+/// One request as it shows up in a test report.
+pub struct TestCase {
+    pub name: String,
+    pub duration: Duration,
+    /// Transport problems, the request never produced a response.
+    pub errors: Vec<String>,
+    /// Responses that did not meet expectations.
+    pub failures: Vec<String>,
+    /// Request file and line the case comes from.
+    pub location: Option<(String, usize)>,
+    /// Not run because an earlier step failed.
+    pub skipped: bool,
+    /// Response head and body of a failed case, see `attach`.
+    pub attachment: Option<String>,
+    /// Request and response of every case for the html report, see `attach_request` and `attach`.
+    pub request: Option<String>,
+    pub response: Option<String>,
+}
+
+impl TestCase {
+    pub fn new(name: &str) -> TestCase {
+        TestCase {
+            name: name.to_string(),
+            duration: Duration::default(),
+            errors: Vec::new(),
+            failures: Vec::new(),
+            location: None,
+            skipped: false,
+            attachment: None,
+            request: None,
+            response: None,
+        }
+    }
+
+    /// Keeps the redacted request for the report, the body cut after `limit` bytes.
+    pub fn attach_request(&mut self, req: &Request, redactor: &Redactor, limit: usize) {
+        if limit == 0 {
+            return;
+        }
+        let body = req.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+        let head = format!("{} {}\n", req.method(), req.url());
+        self.request = Some(message(head, req.headers(), &String::from_utf8_lossy(body), redactor, limit));
+    }
+
+    /// Keeps the redacted response for the report, the body cut after `limit` bytes.
+    /// Failed cases also get it as their attachment.
+    pub fn attach(&mut self, status: u16, headers: &HeaderMap, body: &str, redactor: &Redactor, limit: usize) {
+        if limit == 0 {
+            return;
+        }
+        let text = message(format!("HTTP {}\n", status), headers, body, redactor, limit);
+        if !self.failures.is_empty() {
+            self.attachment = Some(text.clone());
+        }
+        self.response = Some(text);
+    }
+
+    /// A response counts as failed when the server answered with 4xx or 5xx.
+    pub fn from_transaction(transaction: &Transaction) -> TestCase {
+        let mut case = TestCase::new(&format!("{} {}", transaction.method(), transaction.url()));
+        case.duration = transaction.elapsed();
+        if let Some(error) = transaction.error() {
+            case.errors.push(error.to_string());
+        } else if let Some(status) = transaction.status().filter(|s| *s >= 400) {
+            case.failures.push(format!("HTTP status {}", status));
+        }
+        case
+    }
+}
+
+/// `head`, the redacted headers and the body cut after `limit` bytes.
+fn message(mut text: String, headers: &HeaderMap, body: &str, redactor: &Redactor, limit: usize) -> String {
+    for (k, v) in headers {
+        let value = String::from_utf8_lossy(v.as_bytes());
+        text.push_str(&format!("{}: {}\n", k, redactor.display(k.as_str(), &value)));
+    }
+    text.push('\n');
+    if body.len() > limit {
+        let mut end = limit;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.push_str(&body[..end]);
+        text.push_str(&format!("\n... {} more bytes", body.len() - end));
+    } else {
+        text.push_str(body);
+    }
+    text
+}
+
+pub const FORMATS: [&str; 4] = ["junit", "tap", "gha", "html"];
+
+/// Stops on an unknown --report format before any request is sent.
+pub fn check_format(matches: &ArgMatches) {
+    if let Some(format) = matches.values_of("report").and_then(|mut r| r.next()) {
+        if !FORMATS.contains(&format) {
+            panic!("Unknown report format {}, expected one of {}", format, FORMATS.join(", "));
+        }
+    }
+}
+
+/// Writes the cases in the given format to `path`, or stdout when it is `-`.
+pub fn write(format: &str, path: &str, cases: &[&TestCase]) -> Result<(), io::Error> {
+    let report = match format {
+        "junit" => junit(cases),
+        "tap" => tap(cases),
+        "gha" => github_annotations(cases),
+        "html" => html(cases),
+        _ => panic!("Unknown report format {}", format),
+    };
+    if path == "-" {
+        print!("{}", report);
+        Ok(())
+    } else {
+        fs::write(path, report)
+    }
+}
+
+fn junit(cases: &[&TestCase]) -> String {
+    let failures = cases.iter().filter(|c| !c.failures.is_empty()).count();
+    let errors = cases.iter().filter(|c| !c.errors.is_empty()).count();
+    let skipped = cases.iter().filter(|c| c.skipped).count();
+    let total: Duration = cases.iter().map(|c| c.duration).sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n",
+        cases.len(),
+        failures,
+        errors,
+        total.as_secs_f64()
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+        env!("CARGO_PKG_NAME"),
+        cases.len(),
+        failures,
+        errors,
+        skipped,
+        total.as_secs_f64()
+    ));
+    for case in cases {
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+            escape_xml(&case.name),
+            env!("CARGO_PKG_NAME"),
+            case.duration.as_secs_f64()
+        ));
+        if let Some((file, line)) = &case.location {
+            xml.push_str(&format!(" file=\"{}\" line=\"{}\"", escape_xml(file), line));
+        }
+        if case.errors.is_empty() && case.failures.is_empty() && !case.skipped {
+            xml.push_str("/>\n");
+            continue;
+        }
+        xml.push_str(">\n");
+        if case.skipped {
+            xml.push_str("      <skipped/>\n");
+        }
+        for error in &case.errors {
+            xml.push_str(&format!(
+                "      <error message=\"{}\">{}</error>\n",
+                escape_xml(first_line(error)),
+                escape_xml(error)
+            ));
+        }
+        for failure in &case.failures {
+            xml.push_str(&format!(
+                "      <failure message=\"{}\">{}</failure>\n",
+                escape_xml(first_line(failure)),
+                escape_xml(failure)
+            ));
+        }
+        if let Some(attachment) = &case.attachment {
+            xml.push_str(&format!("      <system-out>{}</system-out>\n", escape_xml(attachment)));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+fn tap(cases: &[&TestCase]) -> String {
+    let mut out = format!("TAP version 13\n1..{}\n", cases.len());
+    for (i, case) in cases.iter().enumerate() {
+        let problems: Vec<&String> = case.errors.iter().chain(case.failures.iter()).collect();
+        let status = if problems.is_empty() { "ok" } else { "not ok" };
+        if case.skipped {
+            out.push_str(&format!("ok {} - {} # SKIP\n", i + 1, case.name));
+            continue;
+        }
+        out.push_str(&format!("{} {} - {}\n", status, i + 1, case.name));
+        out.push_str("  ---\n");
+        out.push_str(&format!("  duration_ms: {:.3}\n", case.duration.as_secs_f64() * 1000.0));
+        if !problems.is_empty() {
+            out.push_str("  message: |\n");
+            for problem in problems {
+                for line in problem.lines() {
+                    out.push_str(&format!("    {}\n", line));
+                }
+            }
+        }
+        if let Some(attachment) = &case.attachment {
+            out.push_str("  response: |\n");
+            for line in attachment.lines() {
+                out.push_str(&format!("    {}\n", line));
+            }
+        }
+        out.push_str("  ...\n");
+    }
+    out
+}
+
+/// Workflow commands that GitHub Actions turns into annotations on the run.
+fn github_annotations(cases: &[&TestCase]) -> String {
+    let mut out = String::new();
+    let mut failed = 0;
+    for case in cases {
+        let problems: Vec<&String> = case.errors.iter().chain(case.failures.iter()).collect();
+        if problems.is_empty() {
+            continue;
+        }
+        failed += 1;
+        let message: Vec<&str> = problems.iter().map(|p| p.as_str()).collect();
+        let location = match &case.location {
+            Some((file, line)) => format!("file={},line={},", escape_gha_property(file), line),
+            None => String::new(),
+        };
+        out.push_str(&format!(
+            "::error {}title={}::{}\n",
+            location,
+            escape_gha_property(&case.name),
+            escape_gha_data(&message.join("\n"))
+        ));
+    }
+    let skipped = cases.iter().filter(|c| c.skipped).count();
+    let summary = if skipped > 0 {
+        format!("{} of {} requests passed, {} skipped", cases.len() - failed - skipped, cases.len(), skipped)
+    } else {
+        format!("{} of {} requests passed", cases.len() - failed, cases.len())
+    };
+    out.push_str(&format!(
+        "::notice title={}::{}\n",
+        env!("CARGO_PKG_NAME"),
+        summary
+    ));
+    out
+}
+
+const HTML_STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;width:100%}th,td{text-align:left;padding:4px 8px;border-bottom:1px solid #ddd;vertical-align:top}\
+.pass{color:#1a7f37}.fail{color:#cf222e}.skip{color:#9a6700}.bar{fill:#0969da}.bar.fail{fill:#cf222e}\
+pre{background:#f6f8fa;padding:8px;overflow-x:auto;margin:4px 0}summary{cursor:pointer}";
+
+/// A self-contained page with a summary, a duration chart and one row per case.
+fn html(cases: &[&TestCase]) -> String {
+    let failed = cases.iter().filter(|c| !c.errors.is_empty() || !c.failures.is_empty()).count();
+    let skipped = cases.iter().filter(|c| c.skipped).count();
+    let total: Duration = cases.iter().map(|c| c.duration).sum();
+
+    let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{} report</title>\n", env!("CARGO_PKG_NAME")));
+    out.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", HTML_STYLE));
+    out.push_str(&format!("<h1>{} report</h1>\n", env!("CARGO_PKG_NAME")));
+    out.push_str(&format!(
+        "<p>{} requests, <span class=\"pass\">{} passed</span>, <span class=\"fail\">{} failed</span>, \
+<span class=\"skip\">{} skipped</span>, {:.1} ms in total</p>\n",
+        cases.len(),
+        cases.len() - failed - skipped,
+        failed,
+        skipped,
+        total.as_secs_f64() * 1000.0
+    ));
+    out.push_str(&duration_chart(cases));
+
+    out.push_str("<table>\n<tr><th>#</th><th>Request</th><th>Result</th><th>Time</th><th>Details</th></tr>\n");
+    for (i, case) in cases.iter().enumerate() {
+        let problems: Vec<&String> = case.errors.iter().chain(case.failures.iter()).collect();
+        let (class, result) = if case.skipped {
+            ("skip", "skipped")
+        } else if problems.is_empty() {
+            ("pass", "passed")
+        } else {
+            ("fail", "failed")
+        };
+        let name = match &case.location {
+            Some((file, line)) => format!("{}<br><small>{}:{}</small>", escape_xml(&case.name), escape_xml(file), line),
+            None => escape_xml(&case.name),
+        };
+        out.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td>{:.1} ms</td><td>",
+            i + 1,
+            name,
+            class,
+            result,
+            case.duration.as_secs_f64() * 1000.0
+        ));
+        for problem in problems {
+            out.push_str(&format!("<pre class=\"fail\">{}</pre>", escape_xml(problem)));
+        }
+        for (summary, text) in [("Request", &case.request), ("Response", &case.response)] {
+            if let Some(text) = text {
+                out.push_str(&format!("<details><summary>{}</summary><pre>{}</pre></details>", summary, escape_xml(text)));
+            }
+        }
+        out.push_str("</td></tr>\n");
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+/// Inline SVG with one horizontal bar per case, scaled to the slowest one.
+fn duration_chart(cases: &[&TestCase]) -> String {
+    const WIDTH: f64 = 600.0;
+    const ROW: usize = 18;
+    let slowest = cases.iter().map(|c| c.duration.as_secs_f64()).fold(0.0, f64::max);
+    if cases.is_empty() || slowest == 0.0 {
+        return String::new();
+    }
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+        WIDTH + 120.0,
+        cases.len() * ROW
+    );
+    for (i, case) in cases.iter().enumerate() {
+        let ms = case.duration.as_secs_f64() * 1000.0;
+        let width = case.duration.as_secs_f64() / slowest * WIDTH;
+        let class = if case.errors.is_empty() && case.failures.is_empty() { "bar" } else { "bar fail" };
+        svg.push_str(&format!(
+            "<rect class=\"{}\" x=\"0\" y=\"{}\" width=\"{:.1}\" height=\"{}\"><title>{}</title></rect>\
+<text x=\"{:.1}\" y=\"{}\" font-size=\"12\">{:.1} ms</text>\n",
+            class,
+            i * ROW + 2,
+            width,
+            ROW - 4,
+            escape_xml(&case.name),
+            width + 4.0,
+            i * ROW + ROW - 5,
+            ms
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape_gha_data(text: &str) -> String {
+    text.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+fn escape_gha_property(text: &str) -> String {
+    escape_gha_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if c.is_control() && c != '\n' && c != '\t' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderValue, AUTHORIZATION};
+    use reqwest::{Method, Url};
+
+    #[test]
+    fn html_shows_request_and_response_of_every_case() {
+        let redactor = Redactor::new(Vec::new(), false);
+        let mut req = Request::new(Method::POST, Url::parse("http://example.com/a").unwrap());
+        req.headers_mut().insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        *req.body_mut() = Some("{\"a\":1}".into());
+        let mut passed = TestCase::new("POST /a");
+        passed.attach_request(&req, &redactor, DEFAULT_ATTACH_LIMIT);
+        passed.attach(200, &HeaderMap::new(), "ok", &redactor, DEFAULT_ATTACH_LIMIT);
+        assert!(passed.attachment.is_none());
+
+        let html = html(&[&passed]);
+        assert!(html.contains("<summary>Request</summary><pre>POST http://example.com/a\nauthorization: &lt;redacted&gt;"));
+        assert!(html.contains("\n\n{&quot;a&quot;:1}</pre>"));
+        assert!(html.contains("<summary>Response</summary><pre>HTTP 200\n\nok</pre>"));
+        assert!(!html.contains("secret"));
+    }
+
+    #[test]
+    fn attach_limit_cuts_bodies_and_zero_keeps_nothing() {
+        let redactor = Redactor::new(Vec::new(), false);
+        let mut case = TestCase::new("GET /");
+        case.failures.push("HTTP status 500".to_string());
+        case.attach(500, &HeaderMap::new(), "abcdef", &redactor, 4);
+        assert_eq!(case.attachment.as_deref(), Some("HTTP 500\n\nabcd\n... 2 more bytes"));
+        let mut off = TestCase::new("GET /");
+        off.attach(200, &HeaderMap::new(), "abcdef", &redactor, 0);
+        assert!(off.response.is_none());
+    }
+}
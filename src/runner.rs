@@ -0,0 +1,455 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use clap::ArgMatches;
+use colored::Colorize;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Method, Request};
+use regex::Regex;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use crate::report::TestCase;
+use crate::script::{Action, Op, Predicate, Script, Step, Subject};
+use crate::template::{self, Vars};
+use crate::redirect::{self, Policy};
+use crate::{charset, jsonpath, print_req, print_res, report, script, Redactor};
+
+/// What a step's response looked like, for captures and predicates.
+pub struct Snapshot {
+    /// The request that got the response, for the report.
+    pub request: Option<Request>,
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: String,
+    pub json: Option<Value>,
+    pub duration: Duration,
+}
+
+impl Snapshot {
+    fn resolve(&self, subject: &Subject) -> Result<Option<Value>, String> {
+        Ok(match subject {
+            Subject::Status => Some(Value::from(self.status)),
+            Subject::Header(name) => self
+                .headers
+                .get(name.as_str())
+                .map(|v| Value::String(String::from_utf8_lossy(v.as_bytes()).to_string())),
+            Subject::Body => Some(Value::String(self.body.clone())),
+            Subject::Duration => Some(Value::from(self.duration.as_millis() as u64)),
+            Subject::JsonPath(path) => match &self.json {
+                Some(json) => jsonpath::select(json, path)?.first().map(|v| (*v).clone()),
+                None => None,
+            },
+            Subject::Regex(pattern) => pattern
+                .captures(&self.body)
+                .and_then(|c| c.get(1).or_else(|| c.get(0)))
+                .map(|m| Value::String(m.as_str().to_string())),
+        })
+    }
+}
+
+/// Text form used for comparisons, strings without their quotes.
+fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        other => as_text(other).trim().parse().ok(),
+    }
+}
+
+/// Returns whether the predicate holds for the snapshot, or why it could not be evaluated.
+pub fn holds(predicate: &Predicate, snapshot: &Snapshot) -> Result<bool, String> {
+    let actual = snapshot.resolve(&predicate.subject)?;
+    let expected = predicate.value.as_ref();
+    let (actual, expected) = match (predicate.op, actual, expected) {
+        (Op::Exists, actual, _) => return Ok(actual.is_some()),
+        (_, None, _) => return Ok(false),
+        (_, Some(actual), Some(expected)) => (actual, expected),
+        (_, Some(_), None) => return Ok(false),
+    };
+    Ok(match predicate.op {
+        Op::Eq => actual == *expected || as_text(&actual) == as_text(expected),
+        Op::Ne => actual != *expected && as_text(&actual) != as_text(expected),
+        Op::Contains => match &actual {
+            Value::Array(items) => items.iter().any(|i| i == expected || as_text(i) == as_text(expected)),
+            other => as_text(other).contains(&as_text(expected)),
+        },
+        Op::Matches => Regex::new(&as_text(expected))
+            .map_err(|err| err.to_string())?
+            .is_match(&as_text(&actual)),
+        Op::Lt | Op::Le | Op::Gt | Op::Ge => match (as_number(&actual), as_number(expected)) {
+            (Some(a), Some(e)) => match predicate.op {
+                Op::Lt => a < e,
+                Op::Le => a <= e,
+                Op::Gt => a > e,
+                _ => a >= e,
+            },
+            _ => false,
+        },
+        Op::Exists => unreachable!(),
+    })
+}
+
+/// The failure message of a predicate that did not hold.
+fn describe_failure(at: &str, label: &str, predicate: &Predicate, snapshot: &Snapshot) -> String {
+    let actual = match snapshot.resolve(&predicate.subject) {
+        Ok(Some(value)) => preview(&as_text(&value)),
+        Ok(None) => "<missing>".to_string(),
+        Err(err) => err,
+    };
+    let expected = match &predicate.value {
+        Some(value) => format!("{} {}", op_name(predicate.op), as_text(value)),
+        None => "exists".to_string(),
+    };
+    format!(
+        "{}: {}: {}\n- expected: {}\n+ actual:   {}",
+        at, label, predicate.text, expected, actual
+    )
+}
+
+fn op_name(op: Op) -> &'static str {
+    match op {
+        Op::Eq => "==",
+        Op::Ne => "!=",
+        Op::Contains => "contains",
+        Op::Matches => "matches",
+        Op::Lt => "<",
+        Op::Le => "<=",
+        Op::Gt => ">",
+        Op::Ge => ">=",
+        Op::Exists => "exists",
+    }
+}
+
+fn preview(text: &str) -> String {
+    const MAX: usize = 200;
+    match text.char_indices().nth(MAX) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Runs every step of the request file in order, stopping at the first failing step.
+/// Steps whose `@if ... then skip` condition holds count as skipped and the run goes on.
+/// With --parallel the steps run at the same time instead, see `run_parallel`.
+/// `@teardown` steps run afterwards in any case and are summarized separately.
+/// Returns the exit code.
+pub async fn run(matches: &ArgMatches) -> i32 {
+    report::check_format(matches);
+    let path = matches.value_of("file").unwrap();
+    let script: Script = match script::load(path) {
+        Ok(script) => script,
+        Err(err) => {
+            eprintln!("{}", err);
+            return crate::EXIT_SCRIPT_INVALID;
+        }
+    };
+
+    let runner = Arc::new(Runner {
+        script,
+        // Redirects are followed by redirect::send, as for a request on the command line.
+        client: Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Could not create the http client"),
+        policy: Policy::from_matches(matches),
+        redactor: Redactor::from_matches(matches),
+        verbose: matches.is_present("verbose"),
+        attach_limit: crate::attach_limit(matches),
+    });
+    let mut vars: Vars = template::from_matches(matches);
+    let (cases, mut exit_code) = if matches.is_present("parallel") {
+        run_parallel(&runner, &mut vars).await
+    } else {
+        run_in_order(&runner, &mut vars).await
+    };
+    print_summary("", &cases);
+
+    let mut teardown = Vec::new();
+    let mut teardown_exit_code = 0;
+    for step in runner.script.steps.iter().filter(|s| s.teardown) {
+        if teardown.is_empty() {
+            println!("Teardown:");
+        }
+        let (mut case, code) = runner.step(step, &mut vars).await;
+        case.name = format!("teardown: {}", case.name);
+        if teardown_exit_code == 0 {
+            teardown_exit_code = code;
+        }
+        teardown.push(case);
+    }
+    if !teardown.is_empty() {
+        print_summary("teardown: ", &teardown);
+    }
+    if exit_code == 0 {
+        exit_code = teardown_exit_code;
+    }
+
+    if let Some(mut report) = matches.values_of("report") {
+        let format = report.next().unwrap();
+        let cases: Vec<&TestCase> = cases.iter().chain(teardown.iter()).collect();
+        report::write(format, report.next().unwrap(), &cases).expect("Could not write the report");
+    }
+    exit_code
+}
+
+async fn run_in_order(runner: &Runner, vars: &mut Vars) -> (Vec<TestCase>, i32) {
+    let mut cases = Vec::new();
+    let mut exit_code = 0;
+    for step in runner.script.steps.iter().filter(|s| !s.teardown) {
+        if exit_code != 0 {
+            cases.push(runner.skip(step));
+            continue;
+        }
+        let (case, code) = runner.step(step, vars).await;
+        exit_code = code;
+        cases.push(case);
+    }
+    (cases, exit_code)
+}
+
+/// Starts each step as soon as the steps of its `@after` lines are done, steps without
+/// any start right away. A step after one that failed, or was skipped for that, is
+/// skipped. Captures reach the steps that start after they were taken. The cases come
+/// back in file order, the exit code is that of the first failing step.
+async fn run_parallel(runner: &Arc<Runner>, vars: &mut Vars) -> (Vec<TestCase>, i32) {
+    let steps = &runner.script.steps;
+    // Whether a finished step let the ones after it run.
+    let mut passed: Vec<Option<bool>> = vec![None; steps.len()];
+    let mut results: Vec<Option<(TestCase, i32)>> = steps.iter().map(|_| None).collect();
+    let mut started = vec![false; steps.len()];
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut running = 0;
+    loop {
+        // A skip finishes a step at once, which may let others start.
+        let mut progressed = true;
+        while progressed {
+            progressed = false;
+            for (i, step) in steps.iter().enumerate() {
+                if step.teardown || started[i] || step.after.iter().any(|&d| passed[d].is_none()) {
+                    continue;
+                }
+                started[i] = true;
+                progressed = true;
+                if step.after.iter().any(|&d| passed[d] == Some(false)) {
+                    results[i] = Some((runner.skip(step), 0));
+                    passed[i] = Some(false);
+                    continue;
+                }
+                let runner = Arc::clone(runner);
+                let sender = sender.clone();
+                let mut vars = vars.clone();
+                running += 1;
+                tokio::spawn(async move {
+                    let (case, code) = runner.step(&runner.script.steps[i], &mut vars).await;
+                    let _ = sender.send((i, case, code, vars));
+                });
+            }
+        }
+        if running == 0 {
+            break;
+        }
+        let (i, case, code, captured) = receiver.recv().await.expect("A step of the run panicked");
+        running -= 1;
+        for capture in &steps[i].captures {
+            if let Some(value) = captured.get(&capture.name) {
+                vars.insert(capture.name.clone(), value.clone());
+            }
+        }
+        passed[i] = Some(code == 0);
+        results[i] = Some((case, code));
+    }
+    let results: Vec<(TestCase, i32)> = results.into_iter().flatten().collect();
+    let exit_code = results.iter().map(|(_, code)| *code).find(|code| *code != 0).unwrap_or(0);
+    (results.into_iter().map(|(case, _)| case).collect(), exit_code)
+}
+
+fn print_summary(prefix: &str, cases: &[TestCase]) {
+    let failed = cases.iter().filter(|c| !c.errors.is_empty() || !c.failures.is_empty()).count();
+    let skipped = cases.iter().filter(|c| c.skipped).count();
+    println!(
+        "{}{} steps, {} passed, {} failed, {} skipped",
+        prefix,
+        cases.len(),
+        cases.len() - failed - skipped,
+        failed,
+        skipped
+    );
+}
+
+/// What every step of a run shares.
+struct Runner {
+    script: Script,
+    client: Client,
+    policy: Policy,
+    redactor: Redactor,
+    verbose: bool,
+    attach_limit: usize,
+}
+
+impl Runner {
+    fn location(&self, line: usize) -> Option<(String, usize)> {
+        let origin = self.script.origin(line);
+        Some((origin.file.clone(), origin.line))
+    }
+
+    /// The report entry of a step that is not sent because an earlier one failed.
+    fn skip(&self, step: &Step) -> TestCase {
+        let mut case = TestCase::new(&step.name);
+        case.location = self.location(step.line);
+        case.skipped = true;
+        println!("{} {}", "[SKIP]".yellow(), step.name);
+        case
+    }
+
+    /// Sends the request of one step, checks it and stores its captures in `vars`.
+    /// Returns the report entry and the exit code the step calls for.
+    async fn step(&self, step: &Step, vars: &mut Vars) -> (TestCase, i32) {
+        let at = |line| self.script.at(line);
+        let mut case = TestCase::new(&step.name);
+        case.location = self.location(step.line);
+
+        let started = Instant::now();
+        let mut result = execute(&self.client, &self.policy, step, vars, self.verbose, &self.redactor).await;
+        if let Some(retry) = &step.retry {
+            let mut attempt = 1;
+            while attempt < retry.max && !matches!(&result, Ok(snapshot) if holds(&retry.predicate, snapshot) == Ok(true)) {
+                tokio::time::sleep(retry.every).await;
+                result = execute(&self.client, &self.policy, step, vars, self.verbose, &self.redactor).await;
+                attempt += 1;
+            }
+            if let Ok(snapshot) = &result {
+                if holds(&retry.predicate, snapshot) != Ok(true) {
+                    let label = format!("still failing after {} attempts", attempt);
+                    case.failures.push(describe_failure(&at(retry.predicate.line), &label, &retry.predicate, snapshot));
+                }
+            }
+        }
+        case.duration = started.elapsed();
+        match result {
+            Err(err) => {
+                case.errors.push(format!("{}: {}", at(step.line), err));
+                print_step(&case, None);
+                (case, crate::EXIT_TRANSFER_FAILED)
+            }
+            Ok(snapshot) => {
+                if let Some(request) = &snapshot.request {
+                    case.attach_request(request, &self.redactor, self.attach_limit);
+                }
+                let mut skip = false;
+                for condition in &step.conditions {
+                    match holds(&condition.predicate, &snapshot) {
+                        Ok(true) if condition.action == Action::Skip => skip = true,
+                        Ok(true) => case.failures.push(format!(
+                            "{}: condition matched: {}",
+                            at(condition.predicate.line),
+                            condition.predicate.text
+                        )),
+                        Ok(false) => {}
+                        Err(err) => case.failures.push(format!("{}: {}", at(condition.predicate.line), err)),
+                    }
+                }
+                if skip && case.failures.is_empty() {
+                    case.skipped = true;
+                    println!("{} {}  {}", "[SKIP]".yellow(), step.name, snapshot.status);
+                    case.attach(snapshot.status, &snapshot.headers, &snapshot.body, &self.redactor, self.attach_limit);
+                    return (case, 0);
+                }
+                for predicate in &step.asserts {
+                    match holds(predicate, &snapshot) {
+                        Ok(true) => {}
+                        Ok(false) => case.failures.push(describe_failure(&at(predicate.line), "assertion failed", predicate, &snapshot)),
+                        Err(err) => case.failures.push(format!("{}: {}", at(predicate.line), err)),
+                    }
+                }
+                for capture in &step.captures {
+                    match snapshot.resolve(&capture.subject) {
+                        Ok(Some(value)) => {
+                            vars.insert(capture.name.clone(), as_text(&value));
+                        }
+                        Ok(None) => case.failures.push(format!(
+                            "{}: capture {} found nothing",
+                            at(capture.line),
+                            capture.name
+                        )),
+                        Err(err) => case.failures.push(format!("{}: {}", at(capture.line), err)),
+                    }
+                }
+                case.attach(snapshot.status, &snapshot.headers, &snapshot.body, &self.redactor, self.attach_limit);
+                print_step(&case, Some(snapshot.status));
+                let code = if case.failures.is_empty() { 0 } else { crate::EXIT_EXPECTATION_FAILED };
+                (case, code)
+            }
+        }
+    }
+}
+
+fn print_step(case: &TestCase, status: Option<u16>) {
+    let passed = case.errors.is_empty() && case.failures.is_empty();
+    let label = if passed { "[PASS]".green() } else { "[FAIL]".red() };
+    let status = status.map(|s| s.to_string()).unwrap_or_else(|| "---".to_string());
+    // One print, so the lines of steps that run in parallel do not interleave.
+    let mut text = format!(
+        "{} {}  {}  {:.1} ms\n",
+        label,
+        case.name,
+        status,
+        case.duration.as_secs_f64() * 1000.0
+    );
+    for problem in case.errors.iter().chain(case.failures.iter()) {
+        for line in problem.lines() {
+            text.push_str(&format!("       {}\n", line));
+        }
+    }
+    print!("{}", text);
+}
+
+async fn execute(
+    client: &Client,
+    policy: &Policy,
+    step: &Step,
+    vars: &Vars,
+    verbose: bool,
+    redactor: &Redactor,
+) -> Result<Snapshot, String> {
+    let method = Method::from_bytes(step.method.as_bytes()).map_err(|err| err.to_string())?;
+    let url = crate::parse_url(&template::render(&step.url, vars))?;
+    let mut headers = HeaderMap::new();
+    for (k, v) in &step.headers {
+        let name = HeaderName::from_bytes(k.as_bytes()).map_err(|err| format!("{}: {}", k, err))?;
+        let value = HeaderValue::from_str(&template::render(v, vars)).map_err(|err| format!("{}: {}", k, err))?;
+        headers.append(name, value);
+    }
+    let mut builder = client.request(method, url).headers(headers);
+    if let Some(body) = &step.body {
+        builder = builder.body(template::render(body, vars));
+    }
+    let req = builder.build().map_err(|err| err.to_string())?;
+    if verbose {
+        print_req(&req, redactor);
+    }
+
+    let request = req.try_clone();
+    let started = Instant::now();
+    let response = redirect::send(client, req, policy).await.map_err(|err| err.to_string())?;
+    if verbose {
+        print_res(&response, redactor);
+    }
+    let status = response.status().as_u16();
+    let headers = response.headers().clone();
+    let body = response.bytes().await.map_err(|err| err.to_string())?;
+    let duration = started.elapsed();
+    let body = charset::decode(&headers, &body, None);
+    Ok(Snapshot {
+        request,
+        status,
+        json: serde_json::from_str(&body).ok(),
+        headers,
+        body,
+        duration,
+    })
+}
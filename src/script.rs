@@ -0,0 +1,696 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use regex::Regex;
+use reqwest::header::HeaderName;
+use serde_json::Value;
+use crate::expect;
+
+/// A request file for the `run` subcommand. Entries are separated by `###`
+/// lines, optionally followed by the step name:
+///
+/// ```text
+/// ### login
+/// POST http://localhost/login
+/// Content-Type: application/json
+///
+/// {"user": "admin"}
+///
+/// @capture token = $.token
+/// @assert status == 200
+/// @if status == 409 then skip
+/// @retry-until $.state == "READY" max 10 every 500ms
+///
+/// ### profile
+/// GET http://localhost/me
+/// @after login
+///
+/// ### @teardown logout
+/// DELETE http://localhost/session
+/// ```
+///
+/// `@include FILE` splices in the lines of another file, relative to the one it is written
+/// in. A `### @fragment NAME` block is no step of its own, its lines take the place of each
+/// `@use NAME` line:
+///
+/// ```text
+/// @include common/login.http
+///
+/// ### @fragment auth
+/// Authorization: Bearer {{token}}
+///
+/// ### profile
+/// GET http://localhost/me
+/// @use auth
+/// ```
+pub struct Script {
+    pub steps: Vec<Step>,
+    /// Where each line was written. The `line` of steps, captures and predicates counts
+    /// into it from 1, `at` turns it into `file:line`.
+    pub origins: Vec<Origin>,
+}
+
+/// The file and 1-based line a line of the script comes from.
+pub struct Origin {
+    pub file: String,
+    pub line: usize,
+}
+
+impl Script {
+    pub fn origin(&self, line: usize) -> &Origin {
+        &self.origins[line - 1]
+    }
+
+    /// `file:line` of a line, for messages.
+    pub fn at(&self, line: usize) -> String {
+        let origin = self.origin(line);
+        format!("{}:{}", origin.file, origin.line)
+    }
+}
+
+pub struct Step {
+    pub name: String,
+    /// Line of the request line, 1-based.
+    pub line: usize,
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    pub captures: Vec<Capture>,
+    pub asserts: Vec<Predicate>,
+    pub conditions: Vec<Condition>,
+    pub retry: Option<Retry>,
+    /// `@after NAME, ...`: the indices of the earlier steps this one needs, for `run --parallel`.
+    pub after: Vec<usize>,
+    /// Written as `### @teardown [name]`, runs after the other steps even when they failed.
+    pub teardown: bool,
+}
+
+/// `@if PREDICATE then skip|fail`, checked against the response before the assertions.
+pub struct Condition {
+    pub predicate: Predicate,
+    pub action: Action,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Marks the step as skipped without checking its assertions, the run goes on.
+    Skip,
+    Fail,
+}
+
+/// `@retry-until PREDICATE [max N] [every DURATION]`, sends the request again until the predicate holds.
+pub struct Retry {
+    pub predicate: Predicate,
+    pub max: usize,
+    pub every: Duration,
+}
+
+const DEFAULT_RETRIES: usize = 10;
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub struct Capture {
+    pub name: String,
+    pub subject: Subject,
+    pub line: usize,
+}
+
+/// The part of a response a capture or predicate looks at.
+pub enum Subject {
+    Status,
+    Header(String),
+    Body,
+    Duration,
+    JsonPath(String),
+    /// Only for captures, yields the first group of the first match in the body.
+    Regex(Regex),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Contains,
+    Matches,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Exists,
+}
+
+pub struct Predicate {
+    pub subject: Subject,
+    pub op: Op,
+    pub value: Option<Value>,
+    pub line: usize,
+    /// The predicate as written, used in failure messages.
+    pub text: String,
+}
+
+const METHODS: [&str; 7] = ["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS"];
+
+/// Reads the request file at `path` with its includes and parses it.
+pub fn load(path: &str) -> Result<Script, String> {
+    let mut lines = Vec::new();
+    splice(Path::new(path), &mut Vec::new(), &mut lines)?;
+    parse_lines(lines)
+}
+
+/// Appends the lines of the file at `path` to `lines`, with each `@include FILE` replaced by
+/// the lines of FILE. `stack` holds the files being included, to catch cycles.
+fn splice(path: &Path, stack: &mut Vec<PathBuf>, lines: &mut Vec<(Origin, String)>) -> Result<(), String> {
+    let content = fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    stack.push(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+    let file = path.display().to_string();
+    for (i, line) in content.lines().enumerate() {
+        let at = |message: String| format!("{}: line {}: {}", file, i + 1, message);
+        let target = match line.strip_prefix("@include") {
+            Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => rest.trim(),
+            _ => {
+                lines.push((Origin { file: file.clone(), line: i + 1 }, line.to_string()));
+                continue;
+            }
+        };
+        if target.is_empty() {
+            return Err(at("@include needs a file".to_string()));
+        }
+        let included = path.parent().unwrap_or(Path::new("")).join(target);
+        if stack.contains(&fs::canonicalize(&included).unwrap_or_else(|_| included.clone())) {
+            return Err(at(format!("{} includes itself", target)));
+        }
+        let start = lines.len();
+        splice(&included, stack, lines).map_err(|err| {
+            if err.starts_with("Could not read") { at(err) } else { err }
+        })?;
+        // A blank line at the end of the file would end the headers of the step it is included into.
+        while lines.len() > start && lines.last().is_some_and(|(_, l)| l.trim().is_empty()) {
+            lines.pop();
+        }
+    }
+    stack.pop();
+    Ok(())
+}
+
+/// Parses spliced lines, messages point at the file and line each was written in.
+fn parse_lines(lines: Vec<(Origin, String)>) -> Result<Script, String> {
+    let (origins, texts): (Vec<Origin>, Vec<String>) = lines.into_iter().unzip();
+    let numbered: Vec<(usize, &str)> = texts.iter().enumerate().map(|(i, text)| (i + 1, text.as_str())).collect();
+    let steps = parse_steps(&numbered).map_err(|err| locate(err, &origins))?;
+    Ok(Script { steps, origins })
+}
+
+/// Turns the `line N:` of a parse error into the file and line it refers to.
+fn locate(err: String, origins: &[Origin]) -> String {
+    let located = err
+        .strip_prefix("line ")
+        .and_then(|rest| rest.split_once(": "))
+        .and_then(|(n, message)| Some((origins.get(n.parse::<usize>().ok()?.checked_sub(1)?)?, message)));
+    match located {
+        Some((origin, message)) if origin.file.is_empty() => format!("line {}: {}", origin.line, message),
+        Some((origin, message)) => format!("{}: line {}: {}", origin.file, origin.line, message),
+        None => err,
+    }
+}
+
+/// A `###` block: its title, the line of the `###` and the lines up to the next one.
+struct Section<'a> {
+    title: &'a str,
+    line: usize,
+    lines: Vec<(usize, &'a str)>,
+}
+
+fn parse_steps(lines: &[(usize, &str)]) -> Result<Vec<Step>, String> {
+    let mut sections = vec![Section { title: "", line: 1, lines: Vec::new() }];
+    for &(number, line) in lines {
+        match line.strip_prefix("###") {
+            Some(title) => sections.push(Section { title: title.trim(), line: number, lines: Vec::new() }),
+            None => sections.last_mut().unwrap().lines.push((number, line)),
+        }
+    }
+
+    let mut fragments: HashMap<&str, &[(usize, &str)]> = HashMap::new();
+    for section in &mut sections {
+        if let Some(name) = fragment_name(section.title) {
+            if name.is_empty() {
+                return Err(format!("line {}: @fragment needs a name", section.line));
+            }
+            // Blank lines around a fragment would end the headers of the step that uses it.
+            while section.lines.last().is_some_and(|(_, l)| l.trim().is_empty()) {
+                section.lines.pop();
+            }
+            let blank = section.lines.iter().take_while(|(_, l)| l.trim().is_empty()).count();
+            section.lines.drain(..blank);
+        }
+    }
+    for section in &sections {
+        if let Some(name) = fragment_name(section.title) {
+            if fragments.insert(name, &section.lines).is_some() {
+                return Err(format!("line {}: fragment {} is defined twice", section.line, name));
+            }
+        }
+    }
+
+    let mut steps = Vec::new();
+    for section in &sections {
+        if fragment_name(section.title).is_some() {
+            continue;
+        }
+        let mut title = section.title;
+        let teardown = match title.strip_prefix("@teardown") {
+            Some(rest) => {
+                title = rest.trim();
+                true
+            }
+            None => false,
+        };
+        let name = if title.is_empty() { None } else { Some(title.to_string()) };
+        let mut entry = Vec::new();
+        use_fragments(&section.lines, &fragments, &mut Vec::new(), &mut entry)?;
+        if let Some((mut step, after)) = parse_step(name, teardown, &entry)? {
+            for (name, line) in after {
+                if step.teardown {
+                    return Err(format!("line {}: @teardown steps run last, @after does not apply", line));
+                }
+                // Only earlier steps, so the file order is always a valid order to run in.
+                let index = steps
+                    .iter()
+                    .rposition(|s: &Step| !s.teardown && s.name == name)
+                    .ok_or_else(|| format!("line {}: @after {} names no earlier step", line, name))?;
+                step.after.push(index);
+            }
+            steps.push(step);
+        }
+    }
+    Ok(steps)
+}
+
+/// The name of a `### @fragment NAME` title.
+fn fragment_name(title: &str) -> Option<&str> {
+    title
+        .strip_prefix("@fragment")
+        .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        .map(str::trim)
+}
+
+/// Copies `lines` to `entry` with each `@use NAME` replaced by the lines of that fragment.
+/// `using` holds the fragments being expanded, to catch cycles.
+fn use_fragments<'a>(
+    lines: &[(usize, &'a str)],
+    fragments: &HashMap<&str, &[(usize, &'a str)]>,
+    using: &mut Vec<String>,
+    entry: &mut Vec<(usize, &'a str)>,
+) -> Result<(), String> {
+    for &(number, line) in lines {
+        let name = match line.strip_prefix("@use") {
+            Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => rest.trim(),
+            _ => {
+                entry.push((number, line));
+                continue;
+            }
+        };
+        let fragment = fragments
+            .get(name)
+            .ok_or_else(|| format!("line {}: unknown fragment '{}'", number, name))?;
+        if using.iter().any(|n| n == name) {
+            return Err(format!("line {}: fragment {} uses itself", number, name));
+        }
+        using.push(name.to_string());
+        use_fragments(fragment, fragments, using, entry)?;
+        using.pop();
+    }
+    Ok(())
+}
+
+/// The step names of `@after` lines with their line, until they are resolved to indices.
+type AfterNames = Vec<(String, usize)>;
+
+/// The step of one block, with the step names of its `@after` lines.
+fn parse_step(
+    name: Option<String>,
+    teardown: bool,
+    lines: &[(usize, &str)],
+) -> Result<Option<(Step, AfterNames)>, String> {
+    let mut request_line = None;
+    let mut headers = Vec::new();
+    let mut body: Vec<&str> = Vec::new();
+    let mut captures = Vec::new();
+    let mut asserts = Vec::new();
+    let mut conditions = Vec::new();
+    let mut retry = None;
+    let mut after = Vec::new();
+    let mut in_body = false;
+
+    for (number, line) in lines {
+        let number = *number;
+        if let Some(directive) = line.strip_prefix('@') {
+            let (keyword, rest) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+            match keyword {
+                "capture" => captures.push(parse_capture(rest, number)?),
+                "assert" => asserts.push(parse_predicate(rest, number)?),
+                "if" => conditions.push(parse_condition(rest, number)?),
+                "retry-until" => {
+                    if retry.is_some() {
+                        return Err(format!("line {}: only one @retry-until per request", number));
+                    }
+                    retry = Some(parse_retry(rest, number)?);
+                }
+                "after" => {
+                    let names: Vec<&str> = rest.split(',').map(str::trim).filter(|n| !n.is_empty()).collect();
+                    if names.is_empty() {
+                        return Err(format!("line {}: @after needs a step name", number));
+                    }
+                    after.extend(names.into_iter().map(|n| (n.to_string(), number)));
+                }
+                _ => return Err(format!("line {}: unknown directive @{}", number, keyword)),
+            }
+            continue;
+        }
+        if in_body {
+            body.push(line);
+            continue;
+        }
+        let trimmed = line.trim();
+        if request_line.is_none() {
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("//") {
+                continue;
+            }
+            request_line = Some((number, trimmed));
+            continue;
+        }
+        if trimmed.is_empty() {
+            in_body = true;
+            continue;
+        }
+        if trimmed.starts_with('#') || trimmed.starts_with("//") {
+            continue;
+        }
+        let (k, v) = trimmed
+            .split_once(':')
+            .ok_or_else(|| format!("line {}: expected a 'Name: value' header", number))?;
+        HeaderName::from_bytes(k.trim().as_bytes())
+            .map_err(|_| format!("line {}: invalid header name '{}'", number, k.trim()))?;
+        headers.push((k.trim().to_string(), v.trim().to_string()));
+    }
+
+    let (line, request) = match request_line {
+        Some(request_line) => request_line,
+        None if captures.is_empty() && asserts.is_empty() && conditions.is_empty() && retry.is_none() && after.is_empty() => {
+            return Ok(None)
+        }
+        None => return Err(format!("line {}: directives without a request", lines[0].0)),
+    };
+    let (method, url) = match request.split_once(char::is_whitespace) {
+        Some((method, url)) if METHODS.contains(&method.to_uppercase().as_str()) => {
+            (method.to_uppercase(), url.trim().to_string())
+        }
+        _ if !request.contains(char::is_whitespace) => ("GET".to_string(), request.to_string()),
+        _ => return Err(format!("line {}: expected 'METHOD URL', got {}", line, request)),
+    };
+
+    while body.last().is_some_and(|l| l.trim().is_empty()) {
+        body.pop();
+    }
+    let body = if body.is_empty() { None } else { Some(body.join("\n")) };
+
+    let step = Step {
+        name: name.unwrap_or_else(|| format!("{} {}", method, url)),
+        line,
+        method,
+        url,
+        headers,
+        body,
+        captures,
+        asserts,
+        conditions,
+        retry,
+        after: Vec::new(),
+        teardown,
+    };
+    Ok(Some((step, after)))
+}
+
+/// `NAME = SUBJECT`
+fn parse_capture(text: &str, line: usize) -> Result<Capture, String> {
+    let (name, source) = text
+        .split_once('=')
+        .ok_or_else(|| format!("line {}: expected '@capture name = source'", line))?;
+    let source = source.trim();
+    let subject = match source.strip_prefix("regex ") {
+        Some(pattern) => Subject::Regex(
+            Regex::new(pattern.trim()).map_err(|err| format!("line {}: {}", line, err))?,
+        ),
+        None => {
+            let (subject, rest) = parse_subject(source, line)?;
+            if !rest.is_empty() {
+                return Err(format!("line {}: unexpected '{}' after the capture source", line, rest));
+            }
+            subject
+        }
+    };
+    Ok(Capture {
+        name: name.trim().to_string(),
+        subject,
+        line,
+    })
+}
+
+/// `PREDICATE then skip|fail`
+fn parse_condition(text: &str, line: usize) -> Result<Condition, String> {
+    let (predicate, action) = text
+        .trim()
+        .rsplit_once(" then ")
+        .ok_or_else(|| format!("line {}: expected '@if predicate then skip|fail'", line))?;
+    let action = match action.trim() {
+        "skip" => Action::Skip,
+        "fail" => Action::Fail,
+        other => return Err(format!("line {}: unknown action {}, expected skip or fail", line, other)),
+    };
+    Ok(Condition {
+        predicate: parse_predicate(predicate, line)?,
+        action,
+    })
+}
+
+/// `PREDICATE [max N] [every DURATION]`, the options are read from the end.
+fn parse_retry(text: &str, line: usize) -> Result<Retry, String> {
+    let mut predicate = text.trim();
+    let mut max = DEFAULT_RETRIES;
+    let mut every = DEFAULT_RETRY_DELAY;
+    while let Some((rest, value)) = predicate.rsplit_once(char::is_whitespace) {
+        let (rest, keyword) = rest.trim_end().rsplit_once(char::is_whitespace).unwrap_or(("", rest.trim_end()));
+        match keyword {
+            "max" => {
+                max = value
+                    .parse()
+                    .map_err(|_| format!("line {}: max must be a number, got {}", line, value))?;
+            }
+            "every" => every = parse_duration(value).ok_or_else(|| format!("line {}: invalid duration {}", line, value))?,
+            _ => break,
+        }
+        predicate = rest.trim_end();
+    }
+    if max == 0 {
+        return Err(format!("line {}: max must be at least 1", line));
+    }
+    Ok(Retry {
+        predicate: parse_predicate(predicate, line)?,
+        max,
+        every,
+    })
+}
+
+/// `500ms`, `2s` or a plain number of seconds.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    if let Some(ms) = text.strip_suffix("ms") {
+        return ms.parse().ok().map(Duration::from_millis);
+    }
+    text.strip_suffix('s')
+        .unwrap_or(text)
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// `SUBJECT OP [VALUE]`, e.g. `status == 200` or `header Content-Type contains json`.
+pub fn parse_predicate(text: &str, line: usize) -> Result<Predicate, String> {
+    let (subject, rest) = parse_subject(text.trim(), line)?;
+    let (op, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let op = match op {
+        "==" => Op::Eq,
+        "!=" => Op::Ne,
+        "contains" => Op::Contains,
+        "matches" => Op::Matches,
+        "<" => Op::Lt,
+        "<=" => Op::Le,
+        ">" => Op::Gt,
+        ">=" => Op::Ge,
+        "exists" => Op::Exists,
+        "" => return Err(format!("line {}: missing operator in '{}'", line, text.trim())),
+        other => return Err(format!("line {}: unknown operator {}", line, other)),
+    };
+    let value = value.trim();
+    let value = match (op, value.is_empty()) {
+        (Op::Exists, true) => None,
+        (Op::Exists, false) => return Err(format!("line {}: exists takes no value", line)),
+        (_, true) => return Err(format!("line {}: missing value in '{}'", line, text.trim())),
+        (Op::Matches, false) => {
+            let pattern = match expect::parse_expected(value) {
+                Value::String(pattern) => pattern,
+                other => other.to_string(),
+            };
+            Regex::new(&pattern).map_err(|err| format!("line {}: {}", line, err))?;
+            Some(Value::String(pattern))
+        }
+        (_, false) => Some(expect::parse_expected(value)),
+    };
+    Ok(Predicate {
+        subject,
+        op,
+        value,
+        line,
+        text: text.trim().to_string(),
+    })
+}
+
+fn parse_subject(text: &str, line: usize) -> Result<(Subject, &str), String> {
+    let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let rest = rest.trim_start();
+    match word {
+        "status" => Ok((Subject::Status, rest)),
+        "body" => Ok((Subject::Body, rest)),
+        "duration" => Ok((Subject::Duration, rest)),
+        "header" => {
+            let (name, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if name.is_empty() {
+                return Err(format!("line {}: header needs a name", line));
+            }
+            Ok((Subject::Header(name.to_string()), rest.trim_start()))
+        }
+        path if path.starts_with('$') => Ok((Subject::JsonPath(path.to_string()), rest)),
+        other => Err(format!("line {}: unknown subject {}", line, other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn parse(content: &str) -> Result<Script, String> {
+        let lines = content.lines().enumerate();
+        parse_lines(lines.map(|(i, l)| (Origin { file: String::new(), line: i + 1 }, l.to_string())).collect())
+    }
+
+    const SAMPLE: &str = "### login
+POST http://localhost/login
+Content-Type: application/json
+
+{\"user\": \"admin\"}
+
+@capture token = $.token
+@assert status == 200
+@if status == 409 then skip
+@retry-until $.state == \"READY\" max 3 every 500ms
+
+### @teardown
+DELETE http://localhost/session
+";
+
+    #[test]
+    fn parses_steps_with_directives() {
+        let script = parse(SAMPLE).unwrap();
+        assert_eq!(script.steps.len(), 2);
+        let login = &script.steps[0];
+        assert_eq!(login.name, "login");
+        assert_eq!(login.line, 2);
+        assert_eq!(login.method, "POST");
+        assert_eq!(login.headers, vec![("Content-Type".to_string(), "application/json".to_string())]);
+        assert_eq!(login.body.as_deref(), Some("{\"user\": \"admin\"}"));
+        assert_eq!(login.captures.len(), 1);
+        assert_eq!(login.asserts.len(), 1);
+        assert_eq!(login.conditions.len(), 1);
+        let retry = login.retry.as_ref().unwrap();
+        assert_eq!(retry.max, 3);
+        assert_eq!(retry.every, Duration::from_millis(500));
+        assert!(script.steps[1].teardown);
+        assert_eq!(script.steps[1].name, "DELETE http://localhost/session");
+    }
+
+    #[test]
+    fn errors_carry_the_line() {
+        assert_eq!(parse("GET /\n@nope").err().unwrap(), "line 2: unknown directive @nope");
+        assert!(parse("GET /\nBad Header: x").err().unwrap().starts_with("line 2:"));
+        assert!(parse("GET /\n@assert status").err().unwrap().starts_with("line 2:"));
+        assert!(parse("GET /\n@retry-until status == 200 max 0").is_err());
+        assert!(parse("@assert status == 200").is_err());
+    }
+
+    #[test]
+    fn after_names_earlier_steps() {
+        let script = parse("### a\nGET /a\n### b\nGET /b\n### c\nGET /c\n@after a, b\n").unwrap();
+        assert_eq!(script.steps[2].after, vec![0, 1]);
+        assert_eq!(parse("### a\nGET /a\n@after b\n### b\nGET /b").err().unwrap(), "line 3: @after b names no earlier step");
+        assert!(parse("### a\nGET /a\n### @teardown\nGET /t\n@after a").is_err());
+        assert!(parse("GET /\n@after").is_err());
+    }
+
+    #[test]
+    fn splices_fragments_where_they_are_used() {
+        let script = parse("### @fragment auth\n\nAuthorization: Bearer t\n@assert status == 200\n\n\
+            ### me\nGET http://localhost/me\n@use auth\nAccept: text/plain\n")
+            .unwrap();
+        assert_eq!(script.steps.len(), 1);
+        let me = &script.steps[0];
+        assert_eq!(me.headers.len(), 2);
+        assert_eq!(me.headers[1].0, "Accept");
+        assert_eq!(me.asserts.len(), 1);
+        assert_eq!(me.asserts[0].line, 4);
+        assert_eq!(parse("GET /\n@use nope").err().unwrap(), "line 2: unknown fragment 'nope'");
+        assert!(parse("### @fragment a\n@use a\n### s\nGET /\n@use a").err().unwrap().contains("uses itself"));
+        assert!(parse("### @fragment a\n### @fragment a\n").err().unwrap().contains("defined twice"));
+    }
+
+    #[test]
+    fn includes_files_relative_to_the_including_one() {
+        let dir = std::env::temp_dir().join(format!("rust-curl-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("common")).unwrap();
+        fs::write(dir.join("main.http"), "GET http://localhost/\n@include common/headers.http\n@assert status == 200\n").unwrap();
+        fs::write(dir.join("common/headers.http"), "X-Team: core\n@include bad.http\n\n").unwrap();
+        fs::write(dir.join("common/bad.http"), "X-Bad value\n").unwrap();
+        let main = dir.join("main.http").display().to_string();
+        let bad = dir.join("common").join("bad.http").display().to_string();
+        assert_eq!(load(&main).err().unwrap(), format!("{}: line 1: expected a 'Name: value' header", bad));
+
+        fs::write(dir.join("common/bad.http"), "X-Good: yes\n").unwrap();
+        let script = load(&main).unwrap();
+        assert_eq!(script.steps[0].headers.len(), 2);
+        assert_eq!(script.at(script.steps[0].asserts[0].line), format!("{}:3", main));
+        assert_eq!(script.origin(3).file, bad);
+
+        fs::write(dir.join("common/bad.http"), "@include ../main.http\n").unwrap();
+        assert!(load(&main).err().unwrap().contains("../main.http includes itself"));
+        fs::write(dir.join("common/bad.http"), "@include missing.http\n").unwrap();
+        assert!(load(&main).err().unwrap().contains("line 1: Could not read"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics(content in any::<String>()) {
+            let _ = parse(&content);
+        }
+
+        #[test]
+        fn parse_never_panics_on_directives(line in "@[a-z-]{0,12} [ -~]{0,40}") {
+            let _ = parse(&format!("GET /\n{}", line));
+        }
+
+        #[test]
+        fn parse_predicate_never_panics(text in "[ -~]{0,60}") {
+            let _ = parse_predicate(&text, 1);
+        }
+    }
+}
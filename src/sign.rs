@@ -0,0 +1,178 @@
+use std::time::SystemTime;
+use hmac::{Hmac, Mac};
+use httpdate::fmt_http_date;
+use reqwest::header::{HeaderName, HeaderValue, DATE, HOST};
+use reqwest::Request;
+use sha2::{Digest, Sha256, Sha512};
+use crate::template::{self, Vars};
+
+const DEFAULT_PAYLOAD: &str = "{{method}}\n{{path}}\n{{date}}\n{{body_sha256}}";
+
+/// A parsed `--hmac-sign key=SECRET,algo=sha256,header=X-Signature,payload=TEMPLATE`.
+pub struct HmacSign {
+    key: String,
+    algo: String,
+    header: HeaderName,
+    encoding: String,
+    prefix: String,
+    payload: String,
+}
+
+impl HmacSign {
+    /// The payload has to come last since it may contain commas. `vars` fill the key and the
+    /// prefix; the payload is rendered by `sign`, so that they cannot stand in for what is signed.
+    pub fn parse(spec: &str, vars: &Vars) -> Result<HmacSign, String> {
+        let (options, payload) = match spec.find("payload=") {
+            Some(start) => (&spec[..start], Some(&spec[start + "payload=".len()..])),
+            None => (spec, None),
+        };
+        let mut sign = HmacSign {
+            key: String::new(),
+            algo: "sha256".to_string(),
+            header: HeaderName::from_static("x-signature"),
+            encoding: "hex".to_string(),
+            prefix: String::new(),
+            payload: payload.map(unescape).unwrap_or_else(|| DEFAULT_PAYLOAD.to_string()),
+        };
+        for option in options.split(',').map(|o| o.trim()).filter(|o| !o.is_empty()) {
+            let (name, value) = option
+                .split_once('=')
+                .ok_or_else(|| format!("Unexpected --hmac-sign option {}, expected name=value", option))?;
+            match name {
+                "key" => sign.key = template::render(value, vars),
+                "algo" => sign.algo = value.to_lowercase(),
+                "header" => {
+                    sign.header = HeaderName::from_bytes(value.as_bytes())
+                        .map_err(|_| format!("Invalid --hmac-sign header {}", value))?
+                }
+                "encoding" => sign.encoding = value.to_lowercase(),
+                "prefix" => sign.prefix = template::render(value, vars),
+                _ => return Err(format!("Unknown --hmac-sign option {}", name)),
+            }
+        }
+        if sign.key.is_empty() {
+            return Err("--hmac-sign needs key=SECRET".to_string());
+        }
+        if !["sha256", "sha512"].contains(&sign.algo.as_str()) {
+            return Err(format!("Unsupported --hmac-sign algo {}, expected sha256 or sha512", sign.algo));
+        }
+        if !["hex", "base64"].contains(&sign.encoding.as_str()) {
+            return Err(format!("Unsupported --hmac-sign encoding {}, expected hex or base64", sign.encoding));
+        }
+        Ok(sign)
+    }
+
+    /// Renders the payload for the request and sets the signature header. A Date header is
+    /// added when the payload uses `{{date}}` so that the server sees the signed value.
+    pub fn sign(&self, req: &mut Request, vars: &Vars) -> Result<(), String> {
+        if self.payload.contains("{{date}}") && !req.headers().contains_key(DATE) {
+            let date = HeaderValue::from_str(&fmt_http_date(SystemTime::now())).unwrap();
+            req.headers_mut().insert(DATE, date);
+        }
+        let body = req.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+        let mut parts = vars.clone();
+        parts.insert("method".to_string(), req.method().to_string());
+        parts.insert("path".to_string(), req.url().path().to_string());
+        parts.insert("query".to_string(), req.url().query().unwrap_or_default().to_string());
+        parts.insert(
+            "host".to_string(),
+            req.headers()
+                .get(HOST)
+                .and_then(|h| h.to_str().ok())
+                .map(|h| h.to_string())
+                .or_else(|| crate::host_header(req.url()))
+                .unwrap_or_default(),
+        );
+        parts.insert(
+            "date".to_string(),
+            req.headers().get(DATE).and_then(|d| d.to_str().ok()).unwrap_or_default().to_string(),
+        );
+        parts.insert("body".to_string(), String::from_utf8_lossy(body).to_string());
+        parts.insert("body_sha256".to_string(), hex(&Sha256::digest(body)));
+        let payload = template::render(&self.payload, &parts);
+
+        let signature = match self.algo.as_str() {
+            "sha512" => mac::<Hmac<Sha512>>(&self.key, &payload),
+            _ => mac::<Hmac<Sha256>>(&self.key, &payload),
+        };
+        let signature = match self.encoding.as_str() {
+            "base64" => base64::encode(signature),
+            _ => hex(&signature),
+        };
+        let value = HeaderValue::from_str(&format!("{}{}", self.prefix, signature))
+            .map_err(|_| format!("Invalid --hmac-sign prefix {}", self.prefix))?;
+        req.headers_mut().insert(self.header.clone(), value);
+        Ok(())
+    }
+}
+
+fn mac<M: Mac + hmac::digest::KeyInit>(key: &str, payload: &str) -> Vec<u8> {
+    let mut mac = <M as hmac::digest::KeyInit>::new_from_slice(key.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(payload.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Turns the `\n` and `\t` escapes of a payload given on the command line into the characters.
+fn unescape(payload: &str) -> String {
+    payload.replace("\\n", "\n").replace("\\t", "\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{Method, Url};
+
+    fn signature(spec: &str, vars: &Vars) -> String {
+        let mut req = Request::new(Method::GET, Url::parse("http://example.com/orders?id=7").unwrap());
+        HmacSign::parse(spec, vars).unwrap().sign(&mut req, vars).unwrap();
+        req.headers()["x-signature"].to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn signs_known_vectors() {
+        let vars = Vars::new();
+        let payload = "payload={{method}}\\n{{path}}\\n{{query}}";
+        assert_eq!(
+            signature(&format!("key=secret,{}", payload), &vars),
+            "3a1b98da8f08926b0b102f76539e4906a474cd387aaeab99d1efe7dba162a854"
+        );
+        assert_eq!(
+            signature(&format!("key=secret,encoding=base64,{}", payload), &vars),
+            "OhuY2o8IkmsLEC92U55JBqR0zTh6rquZ0e/n26FiqFQ="
+        );
+        assert_eq!(
+            signature(&format!("key=secret,algo=sha512,{}", payload), &vars),
+            "f9275a0f8dc8df0348f935b78ed79252156999b649d51e4834a38ba48a90bc2e\
+             974530c6ba79b9714b1f6ae4a5da42bdc845232993cf7f07884b6633127ddec4"
+        );
+        assert_eq!(
+            signature(&format!("key=secret,algo=sha512,encoding=base64,{}", payload), &vars),
+            "+SdaD43I3wNI+TW3jteSUhVpmbZJ1R5INKOLpIqQvC6XRTDGunm5cUsfauSl2kK9yEUjKZPPfweIS2YzEn3exA=="
+        );
+    }
+
+    #[test]
+    fn vars_fill_the_key_but_not_the_signed_parts() {
+        let vars: Vars = [("secret", "secret"), ("method", "PUT"), ("path", "/other"), ("scheme", "HMAC ")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(
+            signature("key={{secret}},prefix={{scheme}},payload={{method}}\\n{{path}}\\n{{query}}", &vars),
+            "HMAC 3a1b98da8f08926b0b102f76539e4906a474cd387aaeab99d1efe7dba162a854"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_specs() {
+        let vars = Vars::new();
+        assert!(HmacSign::parse("algo=sha256", &vars).is_err());
+        assert!(HmacSign::parse("key=k,algo=md5", &vars).is_err());
+        assert!(HmacSign::parse("key=k,encoding=hex32", &vars).is_err());
+        assert!(HmacSign::parse("key=k,colour=red", &vars).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use std::env;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use clap::ArgMatches;
+use reqwest::Url;
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+
+/// Everything needed to reach an sftp:// or scp:// url, read from the url and the --ssh-* flags.
+pub struct Target {
+    scp: bool,
+    host: String,
+    port: u16,
+    user: String,
+    password: Option<String>,
+    path: String,
+    key: Option<PathBuf>,
+    key_pass: Option<String>,
+    insecure: bool,
+}
+
+impl Target {
+    pub fn new(url: &Url, matches: &ArgMatches) -> Result<Target, String> {
+        let host = url.host_str().ok_or_else(|| format!("Missing host in {}", url))?;
+        let user = match url.username() {
+            "" => env::var("USER").map_err(|_| format!("Missing user in {}", url))?,
+            user => user.to_string(),
+        };
+        // Like curl, a path starting with /~/ is relative to the home directory.
+        let path = url.path();
+        let path = path.strip_prefix("/~/").unwrap_or(path).to_string();
+        Ok(Target {
+            scp: url.scheme() == "scp",
+            host: host.to_string(),
+            port: url.port().unwrap_or(22),
+            user,
+            password: url.password().map(|p| p.to_string()),
+            path,
+            key: matches.value_of("ssh-key").map(PathBuf::from),
+            key_pass: matches.value_of("ssh-key-pass").map(|p| p.to_string()),
+            insecure: matches.is_present("ssh-insecure"),
+        })
+    }
+
+    /// Reads the remote file.
+    pub fn download(&self) -> Result<Vec<u8>, String> {
+        let session = self.connect()?;
+        let mut data = Vec::new();
+        if self.scp {
+            let (mut channel, _) = session.scp_recv(Path::new(&self.path)).map_err(|err| self.error(err))?;
+            channel.read_to_end(&mut data).map_err(|err| self.error(err))?;
+            close(channel)?;
+        } else {
+            let sftp = session.sftp().map_err(|err| self.error(err))?;
+            let mut file = sftp.open(Path::new(&self.path)).map_err(|err| self.error(err))?;
+            file.read_to_end(&mut data).map_err(|err| self.error(err))?;
+        }
+        Ok(data)
+    }
+
+    /// Writes `data` to the remote file, replacing it.
+    pub fn upload(&self, data: &[u8]) -> Result<(), String> {
+        let session = self.connect()?;
+        if self.scp {
+            let mut channel = session
+                .scp_send(Path::new(&self.path), 0o644, data.len() as u64, None)
+                .map_err(|err| self.error(err))?;
+            channel.write_all(data).map_err(|err| self.error(err))?;
+            channel.send_eof().map_err(|err| self.error(err))?;
+            close(channel)?;
+        } else {
+            let sftp = session.sftp().map_err(|err| self.error(err))?;
+            let mut file = sftp.create(Path::new(&self.path)).map_err(|err| self.error(err))?;
+            file.write_all(data).map_err(|err| self.error(err))?;
+        }
+        Ok(())
+    }
+
+    fn connect(&self) -> Result<Session, String> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .map_err(|err| format!("Could not connect to {}:{}: {}", self.host, self.port, err))?;
+        let mut session = Session::new().map_err(|err| err.to_string())?;
+        session.set_tcp_stream(tcp);
+        session.handshake().map_err(|err| self.error(err))?;
+        if !self.insecure {
+            self.check_host_key(&session)?;
+        }
+        self.authenticate(&session)?;
+        Ok(session)
+    }
+
+    /// Compares the server key with ~/.ssh/known_hosts, unknown hosts are refused.
+    fn check_host_key(&self, session: &Session) -> Result<(), String> {
+        let (key, _) = session.host_key().ok_or("The server sent no host key")?;
+        let mut known_hosts = session.known_hosts().map_err(|err| err.to_string())?;
+        if let Some(file) = home().map(|h| h.join(".ssh/known_hosts")).filter(|f| f.exists()) {
+            known_hosts
+                .read_file(&file, KnownHostFileKind::OpenSSH)
+                .map_err(|err| format!("Could not read {}: {}", file.display(), err))?;
+        }
+        match known_hosts.check_port(&self.host, self.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::Mismatch => Err(format!(
+                "The host key of {} does not match the one in known_hosts",
+                self.host
+            )),
+            CheckResult::NotFound | CheckResult::Failure => Err(format!(
+                "{} is not in ~/.ssh/known_hosts, add it with ssh-keyscan or pass --ssh-insecure",
+                self.host
+            )),
+        }
+    }
+
+    /// Tries the password from the url, then --ssh-key, then the agent and finally the default keys.
+    fn authenticate(&self, session: &Session) -> Result<(), String> {
+        if let Some(password) = &self.password {
+            session
+                .userauth_password(&self.user, password)
+                .map_err(|err| self.error(err))?;
+            return Ok(());
+        }
+        if let Some(key) = &self.key {
+            session
+                .userauth_pubkey_file(&self.user, None, key, self.key_pass.as_deref())
+                .map_err(|err| format!("Could not authenticate with {}: {}", key.display(), err))?;
+            return Ok(());
+        }
+        if session.userauth_agent(&self.user).is_ok() {
+            return Ok(());
+        }
+        let defaults = ["id_ed25519", "id_ecdsa", "id_rsa"];
+        for name in defaults {
+            if let Some(key) = home().map(|h| h.join(".ssh").join(name)).filter(|k| k.exists()) {
+                if session
+                    .userauth_pubkey_file(&self.user, None, &key, self.key_pass.as_deref())
+                    .is_ok()
+                {
+                    return Ok(());
+                }
+            }
+        }
+        Err(format!(
+            "Could not authenticate as {} on {}: no agent identity or key was accepted",
+            self.user, self.host
+        ))
+    }
+
+    fn error(&self, err: impl ToString) -> String {
+        format!("{}:{}: {}", self.host, self.path, err.to_string())
+    }
+}
+
+fn close(mut channel: ssh2::Channel) -> Result<(), String> {
+    channel.wait_eof().map_err(|err| err.to_string())?;
+    channel.close().map_err(|err| err.to_string())?;
+    channel.wait_close().map_err(|err| err.to_string())
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME").map(PathBuf::from)
+}
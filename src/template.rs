@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use clap::ArgMatches;
+
+pub type Vars = HashMap<String, String>;
+
+/// Replaces `{{name}}` placeholders with their values, unknown names are left as they are.
+pub fn render(input: &str, vars: &Vars) -> String {
+    if vars.is_empty() {
+        return input.to_string();
+    }
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                match vars.get(name) {
+                    Some(value) => out.push_str(value),
+                    None => out.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Collects the variables of a command: environment variables, then the
+/// --var-file entries and finally the --var flags, later ones winning.
+pub fn from_matches(matches: &ArgMatches) -> Vars {
+    let mut vars: Vars = env::vars().collect();
+    if let Some(path) = matches.value_of("var-file") {
+        let content = fs::read_to_string(path).expect("Could not read the var file");
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = parse_var(line);
+            vars.insert(name, value);
+        }
+    }
+    for var in matches.values_of("var").unwrap_or_default() {
+        let (name, value) = parse_var(var);
+        vars.insert(name, value);
+    }
+    vars
+}
+
+/// `name=value`, the value may be quoted.
+fn parse_var(var: &str) -> (String, String) {
+    let (name, value) = var
+        .split_once('=')
+        .unwrap_or_else(|| panic!("Unexpected variable format {}, expected name=value", var));
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value);
+    (name.trim().to_string(), value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn vars() -> Vars {
+        Vars::from([("host".to_string(), "example.com".to_string())])
+    }
+
+    #[test]
+    fn replaces_known_and_keeps_unknown_placeholders() {
+        assert_eq!(render("http://{{ host }}/{{path}}", &vars()), "http://example.com/{{path}}");
+        assert_eq!(render("{{host", &vars()), "{{host");
+        assert_eq!(parse_var("a = 'b c'"), ("a".to_string(), "b c".to_string()));
+    }
+
+    proptest! {
+        #[test]
+        fn render_never_panics(input in any::<String>()) {
+            let _ = render(&input, &vars());
+        }
+
+        #[test]
+        fn render_without_placeholders_is_identity(input in "[^{]*") {
+            prop_assert_eq!(render(&input, &vars()), input);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use clap::ArgMatches;
+use reqwest::{Request, Response};
+use crate::{host_header, request_target, Redactor};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TraceMode {
+    Hex,
+    Ascii,
+}
+
+/// Dumps everything that goes over the HTTP layer in the style of curl's `--trace`.
+pub struct Tracer {
+    mode: TraceMode,
+    out: Box<dyn Write>,
+    timestamps: bool,
+}
+
+impl Tracer {
+    /// Returns `None` when neither `--trace` nor `--trace-ascii` was given.
+    /// A target of `-` writes the dump to stderr.
+    pub fn from_matches(matches: &ArgMatches) -> Option<Tracer> {
+        let (mode, target) = if let Some(target) = matches.value_of("trace") {
+            (TraceMode::Hex, target)
+        } else {
+            (TraceMode::Ascii, matches.value_of("trace-ascii")?)
+        };
+        let out: Box<dyn Write> = if target == "-" {
+            Box::new(io::stderr())
+        } else {
+            Box::new(File::create(target).expect("Could not create the trace file"))
+        };
+        Some(Tracer {
+            mode,
+            out,
+            timestamps: matches.is_present("trace-time"),
+        })
+    }
+
+    pub fn info(&mut self, message: &str) {
+        let line = format!("{}== Info: {}\n", self.timestamp(), message);
+        self.write(line.as_bytes());
+    }
+
+    pub fn send_header(&mut self, req: &Request, redactor: &Redactor) {
+        self.block("=> Send header", request_head(req, redactor).as_bytes());
+    }
+
+    pub fn send_data(&mut self, data: &[u8]) {
+        self.block("=> Send data", data);
+    }
+
+    pub fn recv_header(&mut self, res: &Response, redactor: &Redactor) {
+        self.block("<= Recv header", response_head(res, redactor).as_bytes());
+    }
+
+    pub fn recv_data(&mut self, data: &[u8]) {
+        self.block("<= Recv data", data);
+    }
+
+    fn block(&mut self, title: &str, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let mut dump = format!(
+            "{}{}, {} bytes (0x{:x})\n",
+            self.timestamp(),
+            title,
+            data.len(),
+            data.len()
+        );
+        match self.mode {
+            TraceMode::Hex => dump.push_str(&hex_dump(data)),
+            TraceMode::Ascii => dump.push_str(&ascii_dump(data)),
+        }
+        self.write(dump.as_bytes());
+    }
+
+    fn timestamp(&self) -> String {
+        if !self.timestamps {
+            return String::new();
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = now.as_secs() % 86400;
+        format!(
+            "{:02}:{:02}:{:02}.{:06} ",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            now.subsec_micros()
+        )
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        self.out.write_all(data).expect("Could not write the trace");
+    }
+}
+
+fn request_head(req: &Request, redactor: &Redactor) -> String {
+    let mut head = format!("{} {} {:?}\r\n", req.method(), request_target(req.url()), req.version());
+    if !req.headers().contains_key("host") {
+        if let Some(host) = host_header(req.url()) {
+            head.push_str(&format!("Host: {}\r\n", host));
+        }
+    }
+    for (k, v) in req.headers() {
+        let value = String::from_utf8_lossy(v.as_bytes());
+        head.push_str(&format!("{}: {}\r\n", k, redactor.display(k.as_str(), &value)));
+    }
+    head.push_str("\r\n");
+    head
+}
+
+fn response_head(res: &Response, redactor: &Redactor) -> String {
+    let mut head = format!("{:?} {}\r\n", res.version(), res.status());
+    for (k, v) in res.headers() {
+        let value = String::from_utf8_lossy(v.as_bytes());
+        head.push_str(&format!("{}: {}\r\n", k, redactor.display(k.as_str(), &value)));
+    }
+    head.push_str("\r\n");
+    head
+}
+
+fn printable(byte: u8) -> char {
+    if byte.is_ascii_graphic() || byte == b' ' {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+/// Sixteen bytes per row: offset, hex columns and the printable characters.
+pub fn hex_dump(data: &[u8]) -> String {
+    let mut dump = String::new();
+    for (row, chunk) in data.chunks(16).enumerate() {
+        dump.push_str(&format!("{:04x}: ", row * 16));
+        for i in 0..16 {
+            match chunk.get(i) {
+                Some(byte) => dump.push_str(&format!("{:02x} ", byte)),
+                None => dump.push_str("   "),
+            }
+        }
+        dump.extend(chunk.iter().map(|b| printable(*b)));
+        dump.push('\n');
+    }
+    dump
+}
+
+/// Splits the data on line feeds so that protocol text stays readable.
+fn ascii_dump(data: &[u8]) -> String {
+    let mut dump = String::new();
+    let mut offset = 0;
+    for line in data.split_inclusive(|b| *b == b'\n') {
+        dump.push_str(&format!("{:04x}: ", offset));
+        dump.extend(
+            line.iter()
+                .filter(|b| **b != b'\r' && **b != b'\n')
+                .map(|b| printable(*b)),
+        );
+        dump.push('\n');
+        offset += line.len();
+    }
+    dump
+}
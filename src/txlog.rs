@@ -0,0 +1,111 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use reqwest::Request;
+use serde_json::json;
+
+/// Collects what `--log-json` writes about a single request.
+pub struct Transaction {
+    method: String,
+    url: String,
+    started_at: SystemTime,
+    started: Instant,
+    ttfb: Option<Duration>,
+    total: Option<Duration>,
+    status: Option<u16>,
+    bytes_sent: usize,
+    bytes_received: usize,
+    error: Option<String>,
+}
+
+impl Transaction {
+    pub fn start(req: &Request) -> Transaction {
+        Transaction {
+            method: req.method().to_string(),
+            url: req.url().to_string(),
+            started_at: SystemTime::now(),
+            started: Instant::now(),
+            ttfb: None,
+            total: None,
+            status: None,
+            bytes_sent: req.body().and_then(|b| b.as_bytes()).map_or(0, |b| b.len()),
+            bytes_received: 0,
+            error: None,
+        }
+    }
+
+    pub fn headers_received(&mut self, status: u16) {
+        self.ttfb = Some(self.started.elapsed());
+        self.status = Some(status);
+    }
+
+    pub fn body_received(&mut self, len: usize) {
+        self.bytes_received = len;
+        self.total = Some(self.started.elapsed());
+    }
+
+    pub fn failed(&mut self, error: impl ToString) {
+        self.error = Some(error.to_string());
+        self.total.get_or_insert(self.started.elapsed());
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    /// Time until the body was received or the request failed, or so far when neither happened yet.
+    pub fn elapsed(&self) -> Duration {
+        self.total.unwrap_or_else(|| self.started.elapsed())
+    }
+
+    pub fn ttfb(&self) -> Option<Duration> {
+        self.ttfb
+    }
+
+    pub fn bytes_sent(&self) -> usize {
+        self.bytes_sent
+    }
+
+    pub fn bytes_received(&self) -> usize {
+        self.bytes_received
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Appends the transaction as a single JSON line to `path`.
+    pub fn append_to(&self, path: &str) -> Result<(), io::Error> {
+        let timestamp = self
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let record = json!({
+            "timestamp": timestamp,
+            "method": self.method,
+            "url": self.url,
+            "status": self.status,
+            "time_ttfb_ms": self.ttfb.map(|d| d.as_secs_f64() * 1000.0),
+            "time_total_ms": self.elapsed().as_secs_f64() * 1000.0,
+            "bytes_sent": self.bytes_sent,
+            "bytes_received": self.bytes_received,
+            "error": self.error,
+        });
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", record)
+    }
+}
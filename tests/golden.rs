@@ -0,0 +1,568 @@
+//! End-to-end runs of the binary against a small mock server. Each case compares
+//! the exit code, stdout and stderr with `tests/golden/<name>.out`; run with
+//! `UPDATE_GOLDEN=1` to rewrite the files after an intended change.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+/// Starts the mock server once for all tests and returns its port.
+fn server() -> u16 {
+    static PORT: OnceLock<u16> = OnceLock::new();
+    *PORT.get_or_init(|| {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                thread::spawn(move || handle(stream));
+            }
+        });
+        port
+    })
+}
+
+/// 4096 bytes that are not valid UTF-8, the same on every call.
+fn binary() -> Vec<u8> {
+    let mut state: u32 = 0x2545_f491;
+    (0..4096)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Requests /flaky has seen, every third one is READY.
+static FLAKY_HITS: AtomicUsize = AtomicUsize::new(0);
+
+fn handle(mut stream: TcpStream) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut headers = Vec::new();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+            break;
+        }
+        let (name, value) = line.trim().split_once(':').unwrap_or((line.trim(), ""));
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+        headers.push(format!("{}: {}", name.to_lowercase(), value.trim()));
+    }
+    let mut body = vec![0; content_length];
+    let _ = reader.read_exact(&mut body);
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let (status, content_type, extra, payload) = route(&method, &path, &headers, &body);
+    // The head goes out as ISO-8859-1, so routes can send obs-text header values.
+    let mut response: Vec<u8> = format!(
+        "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n{}\r\n",
+        status,
+        content_type,
+        payload.len(),
+        extra
+    )
+    .chars()
+    .map(|c| c as u8)
+    .collect();
+    if method != "HEAD" {
+        response.extend_from_slice(&payload);
+    }
+    let _ = stream.write_all(&response);
+}
+
+fn route(
+    method: &str,
+    path: &str,
+    headers: &[String],
+    body: &[u8],
+) -> (&'static str, &'static str, String, Vec<u8>) {
+    let json = "application/json";
+    match path {
+        "/json" => (
+            "200 OK",
+            json,
+            String::new(),
+            br#"{"state":"READY","token":"t0k","items":[{"id":1},{"id":2}]}"#.to_vec(),
+        ),
+        "/redirect" => ("302 Found", "text/plain", "location: /json\r\n".to_string(), Vec::new()),
+        "/binary" => {
+            let data = binary();
+            let range = headers
+                .iter()
+                .find_map(|h| h.strip_prefix("range: bytes="))
+                .and_then(|r| r.split_once('-'))
+                .and_then(|(start, end)| Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?)));
+            match range {
+                Some((start, end)) => (
+                    "206 Partial Content",
+                    "application/octet-stream",
+                    format!("content-range: bytes {}-{}/{}\r\n", start, end, data.len()),
+                    data[start..=end].to_vec(),
+                ),
+                None => ("200 OK", "application/octet-stream", "accept-ranges: bytes\r\n".to_string(), data),
+            }
+        }
+        "/obs-text" => ("200 OK", "text/plain", "x-name: caf\u{e9}\r\n".to_string(), b"ok".to_vec()),
+        "/cross-host" => {
+            // The same server under another name, so the redirect leaves the host.
+            let port = headers.iter().find_map(|h| h.strip_prefix("host: 127.0.0.1:")).unwrap_or_default();
+            ("302 Found", "text/plain", format!("location: http://localhost:{}/echo\r\n", port), Vec::new())
+        }
+        // Long enough that steps running beside it finish first.
+        "/slow" => {
+            thread::sleep(Duration::from_millis(400));
+            ("200 OK", "text/plain", String::new(), b"slow".to_vec())
+        }
+        "/big" => ("200 OK", "text/plain", String::new(), vec![b'x'; 1000]),
+        "/flaky" => {
+            if FLAKY_HITS.fetch_add(1, Ordering::SeqCst) % 3 < 2 {
+                ("200 OK", json, String::new(), br#"{"state":"PENDING"}"#.to_vec())
+            } else {
+                ("200 OK", json, String::new(), br#"{"state":"READY"}"#.to_vec())
+            }
+        }
+        "/echo" => {
+            let mut headers: Vec<&String> = headers.iter().filter(|h| !h.starts_with("host:")).collect();
+            headers.sort();
+            let text = format!(
+                "{} {}\n{}\n\n{}",
+                method,
+                path,
+                headers.iter().map(|h| h.as_str()).collect::<Vec<_>>().join("\n"),
+                String::from_utf8_lossy(body)
+            );
+            ("200 OK", "text/plain", String::new(), text.into_bytes())
+        }
+        _ if path.starts_with("/items/") => {
+            let id = &path["/items/".len()..];
+            ("200 OK", json, String::new(), format!(r#"{{"id":"{}"}}"#, id).into_bytes())
+        }
+        "/status/404" => ("404 Not Found", "text/plain", String::new(), b"missing".to_vec()),
+        "/status/503" => ("503 Service Unavailable", "text/plain", String::new(), b"down".to_vec()),
+        _ => ("404 Not Found", "text/plain", String::new(), b"not found".to_vec()),
+    }
+}
+
+/// Runs the binary with `{port}` in the arguments replaced and returns the normalized transcript.
+fn transcript(args: &[&str], files: &[(&str, &str)]) -> String {
+    let port = server().to_string();
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&dir).unwrap();
+    for (name, content) in files {
+        fs::write(dir.join(name), content.replace("{port}", &port)).unwrap();
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-curl"))
+        .args(args.iter().map(|a| a.replace("{port}", &port)))
+        .current_dir(&dir)
+        .env("NO_COLOR", "1")
+        .env_remove("TRACEPARENT")
+        .output()
+        .unwrap();
+    let text = format!(
+        "exit: {}\n--- stdout\n{}--- stderr\n{}",
+        output.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    normalize(&text.replace(&port, "{port}"))
+}
+
+/// Masks what changes between runs: timings and dates.
+fn normalize(text: &str) -> String {
+    let ms = Regex::new(r"\d+\.\d+ ms|duration_ms: \d+\.\d+").unwrap();
+    let date = Regex::new(r"\w{3}, \d{2} \w{3} \d{4} \d{2}:\d{2}:\d{2} GMT").unwrap();
+    let text = ms.replace_all(text, "<ms>");
+    date.replace_all(&text, "<date>").to_string()
+}
+
+fn check(name: &str, args: &[&str], files: &[(&str, &str)]) {
+    let actual = transcript(args, files);
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.out", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("Missing {}, run with UPDATE_GOLDEN=1 to create it", path.display()));
+    assert_eq!(actual, expected, "transcript of {} changed", name);
+}
+
+#[test]
+fn follows_redirects() {
+    check("redirect", &["-X", "GET", "http://127.0.0.1:{port}/redirect"], &[]);
+}
+
+#[test]
+fn drops_credentials_on_cross_host_redirects() {
+    check(
+        "redirect_cross_host",
+        &[
+            "-v", "-X", "GET", "http://127.0.0.1:{port}/cross-host",
+            "-H", "Authorization: Bearer secret", "-H", "Cookie: session=1", "-H", "X-Kept: yes",
+        ],
+        &[],
+    );
+    check(
+        "redirect_cross_host_trusted",
+        &[
+            "--location-trusted", "-X", "GET", "http://127.0.0.1:{port}/cross-host",
+            "-H", "Authorization: Bearer secret",
+        ],
+        &[],
+    );
+}
+
+#[test]
+fn method_defaults_to_get_or_post() {
+    check("default_get", &["http://127.0.0.1:{port}/echo"], &[]);
+    check("default_post", &["http://127.0.0.1:{port}/echo", "-d", "a=1"], &[]);
+    check("lowercase_method", &["-X", "put", "http://127.0.0.1:{port}/echo"], &[]);
+}
+
+#[test]
+fn verbose_redacts_credentials() {
+    check(
+        "verbose_redacted",
+        &["-v", "-X", "GET", "http://127.0.0.1:{port}/json", "-H", "Authorization: Bearer secret"],
+        &[],
+    );
+}
+
+#[test]
+fn verbose_shows_obs_text_headers() {
+    check("verbose_obs_text", &["-v", "-X", "GET", "http://127.0.0.1:{port}/obs-text"], &[]);
+}
+
+#[test]
+fn sends_data_and_templated_headers() {
+    check(
+        "post_echo",
+        &[
+            "-X", "POST", "http://127.0.0.1:{port}/echo",
+            "-H", "X-Env: {{STAGE}}", "--var", "STAGE=test", "-d", "{\"a\":1}",
+        ],
+        &[],
+    );
+}
+
+#[test]
+fn failed_expectations_exit_90() {
+    check(
+        "expect_failed",
+        &[
+            "-X", "GET", "http://127.0.0.1:{port}/json",
+            "--expect-status", "2xx", "--expect-json", "$.state=DONE",
+        ],
+        &[],
+    );
+}
+
+#[test]
+fn falls_back_to_mirrors() {
+    check(
+        "mirror",
+        &[
+            "-v", "-X", "GET", "http://127.0.0.1:{port}/status/503",
+            "--mirror", "http://127.0.0.1:{port}/items/7",
+        ],
+        &[],
+    );
+}
+
+#[test]
+fn assumes_http_with_a_url_in_the_query() {
+    check("dry_run_query_url", &["--dry-run", "-X", "GET", "127.0.0.1:{port}/r?to=https://x"], &[]);
+}
+
+#[test]
+fn malformed_url_exits_3() {
+    check("malformed_url", &["-X", "GET", "http://exa mple.com/"], &[]);
+}
+
+#[test]
+fn races_each_host_with_happy_eyeballs() {
+    check(
+        "happy_eyeballs",
+        &[
+            "-v", "-X", "GET", "http://localhost:1/json", "--happy-eyeballs-timeout-ms", "50",
+            "--mirror", "http://localhost:{port}/json",
+        ],
+        &[],
+    );
+    check(
+        "happy_eyeballs_failed",
+        &["-X", "GET", "http://localhost:1/json", "--happy-eyeballs-timeout-ms", "50"],
+        &[],
+    );
+}
+
+#[test]
+fn max_filesize_exits_63() {
+    check("max_filesize", &["-X", "GET", "http://127.0.0.1:{port}/big", "--max-filesize", "100"], &[]);
+}
+
+#[test]
+fn checksum_mismatch_exits_91() {
+    check(
+        "checksum",
+        &["-X", "GET", "http://127.0.0.1:{port}/big", "--expected-sha256", "00", "-o", "big.txt"],
+        &[],
+    );
+}
+
+#[test]
+fn saves_binary_bodies_unchanged() {
+    let hash = sha256_hex(&binary());
+    check(
+        "binary",
+        &["-X", "GET", "http://127.0.0.1:{port}/binary", "--expected-sha256", &hash, "-o", "binary.dat"],
+        &[],
+    );
+    let saved = fs::read(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden/binary.dat")).unwrap();
+    assert_eq!(sha256_hex(&saved), hash);
+}
+
+#[test]
+fn keeps_frontmatter_out_of_binary_files() {
+    let hash = sha256_hex(&binary());
+    transcript(&["-X", "GET", "http://127.0.0.1:{port}/binary", "--with-frontmatter", "-o", "framed.dat"], &[]);
+    let saved = fs::read(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden/framed.dat")).unwrap();
+    assert_eq!(sha256_hex(&saved), hash);
+}
+
+#[test]
+fn joins_parallel_chunks_unchanged() {
+    let hash = sha256_hex(&binary());
+    check(
+        "parallel_chunks",
+        &[
+            "-v", "-X", "GET", "http://127.0.0.1:{port}/binary",
+            "--parallel-chunks", "4", "--expected-sha256", &hash, "-o", "chunks.dat",
+        ],
+        &[],
+    );
+    let saved = fs::read(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden/chunks.dat")).unwrap();
+    assert_eq!(sha256_hex(&saved), hash);
+}
+
+/// file:// goes through the same content response as sftp:// and scp:// downloads.
+#[test]
+fn saves_binary_files_from_local_urls_unchanged() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("local.bin"), binary()).unwrap();
+    let hash = sha256_hex(&binary());
+    let url = format!("file://{}", dir.join("local.bin").display());
+    check(
+        "binary_file_url",
+        &["-X", "GET", &url, "--expected-sha256", &hash, "-o", "local.dat"],
+        &[],
+    );
+    assert_eq!(sha256_hex(&fs::read(dir.join("local.dat")).unwrap()), hash);
+}
+
+#[test]
+fn pipes_binary_bodies_unchanged() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-curl"))
+        .args(["-X", "GET", &format!("http://127.0.0.1:{}/binary", server())])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(sha256_hex(&output.stdout), sha256_hex(&binary()));
+}
+
+#[test]
+fn fans_out_over_a_list() {
+    check(
+        "fan_out",
+        &[
+            "-X", "GET", "http://127.0.0.1:{port}/json",
+            "--fan-out", "$.items[*].id", "--then", "GET /items/{{id}}",
+        ],
+        &[],
+    );
+}
+
+#[test]
+fn fan_out_over_a_non_json_response_fails() {
+    check(
+        "fan_out_not_json",
+        &[
+            "-X", "GET", "http://127.0.0.1:{port}/status/404",
+            "--fan-out", "$.items[*].id", "--then", "GET /items/{{id}}",
+        ],
+        &[],
+    );
+}
+
+#[test]
+fn runs_request_files_with_retries_and_teardown() {
+    let script = "### login
+GET http://127.0.0.1:{port}/json
+@capture token = $.token
+@assert status == 200
+
+### wait
+GET http://127.0.0.1:{port}/flaky
+Authorization: Bearer {{token}}
+@retry-until $.state == READY max 5 every 10ms
+
+### missing
+GET http://127.0.0.1:{port}/status/404
+@assert status == 200
+
+### skipped
+GET http://127.0.0.1:{port}/json
+
+### @teardown cleanup
+DELETE http://127.0.0.1:{port}/items/{{token}}
+@assert $.id == t0k
+";
+    check(
+        "run",
+        &["run", "steps.http", "--report", "tap", "-", "--attach-limit", "0"],
+        &[("steps.http", script)],
+    );
+}
+
+#[test]
+fn run_redacts_headers_in_verbose_output() {
+    let script = "GET http://127.0.0.1:{port}/json
+Authorization: Bearer secret
+X-Api-Key: k3y
+";
+    check(
+        "run_redacted",
+        &["run", "redact.http", "-v", "--redact-header", "X-Api-Key"],
+        &[("redact.http", script)],
+    );
+}
+
+#[test]
+fn rejects_invalid_request_files() {
+    check("run_invalid", &["run", "bad.http"], &[("bad.http", "GET /\n@nope\n")]);
+}
+
+#[test]
+fn run_checks_the_report_format_before_any_step() {
+    let script = "GET http://127.0.0.1:{port}/json\n";
+    let text = transcript(&["run", "steps.http", "--report", "nope", "-"], &[("steps.http", script)]);
+    assert!(text.starts_with("exit: 101\n--- stdout\n--- stderr\n"), "{}", text);
+    assert!(text.contains("Unknown report format nope"), "{}", text);
+}
+
+#[test]
+fn run_follows_redirects_like_the_command_line() {
+    let script = "GET http://127.0.0.1:{port}/redirect
+@assert status == 200
+";
+    check("run_redirect", &["run", "redirect.http", "-v"], &[("redirect.http", script)]);
+}
+
+#[test]
+fn sni_name_on_an_unresolvable_host_is_a_transfer_failure() {
+    let text = transcript(&["-X", "GET", "--sni-name", "example.com", "http://nothing.invalid/"], &[]);
+    assert!(text.starts_with("exit: 1\n"), "{}", text);
+    assert!(text.contains("Could not resolve nothing.invalid"), "{}", text);
+}
+
+#[test]
+fn run_shares_fragments_through_includes() {
+    let script = "@include shared.http
+
+### me
+GET http://127.0.0.1:{port}/echo
+@use auth
+@assert body contains authorization: Bearer t0k
+";
+    let shared = "### @fragment auth
+Authorization: Bearer t0k
+@assert status == 201
+";
+    check(
+        "run_include",
+        &["run", "suite.http"],
+        &[("suite.http", script), ("shared.http", shared)],
+    );
+}
+
+#[test]
+fn run_parallel_follows_after_chains() {
+    let script = "### slow
+GET http://127.0.0.1:{port}/slow
+
+### login
+GET http://127.0.0.1:{port}/json
+@capture token = $.token
+
+### item
+GET http://127.0.0.1:{port}/items/{{token}}
+@after login
+@assert $.id == t0k
+
+### missing
+GET http://127.0.0.1:{port}/status/404
+@after item
+@assert status == 200
+
+### never
+GET http://127.0.0.1:{port}/json
+@after missing, login
+";
+    check(
+        "run_parallel",
+        &["run", "suite.http", "--parallel", "--report", "tap", "-", "--attach-limit", "0"],
+        &[("suite.http", script)],
+    );
+}
+
+#[test]
+fn min_success_rate_tolerates_some_failures() {
+    let urls = ["http://127.0.0.1:{port}/{json,status/404,status/503,obs-text}", "-X", "GET"];
+    check("min_success_rate_met", &[&urls[..], &["--min-success-rate", "50%"]].concat(), &[]);
+    check("min_success_rate_missed", &[&urls[..], &["--min-success-rate", "75"]].concat(), &[]);
+}
+
+#[test]
+fn batch_progress_counts_files_and_bytes() {
+    let text = transcript(&["-X", "GET", "http://127.0.0.1:{port}/{json,obs-text}", "--batch-progress"], &[]);
+    assert!(text.contains("Batch: 1/2 done, 59 B received, "), "{}", text);
+    assert!(text.contains("Batch: 2/2 done, 61 B received, "), "{}", text);
+    assert!(text.contains(", ETA 0s\n"), "{}", text);
+    let text = transcript(&["-X", "GET", "http://127.0.0.1:{port}/[1-2]", "--batch-progress", "--output-format", "json"], &[]);
+    let line = text.lines().find(|l| l.starts_with(r#"{"batch""#)).unwrap();
+    let progress: serde_json::Value = serde_json::from_str(line).unwrap();
+    assert_eq!(progress["batch"]["done"], 1);
+    assert_eq!(progress["batch"]["total"], 2);
+}
+
+#[test]
+fn url_file_priorities_come_first() {
+    let urls = "http://127.0.0.1:{port}/obs-text
+http://127.0.0.1:{port}/json priority=5
+http://127.0.0.1:{port}/status/404 missing.txt priority=-1
+http://127.0.0.1:{port}/redirect priority=5
+";
+    check("url_file_priority", &["-X", "GET", "--url-file", "urls.txt"], &[("urls.txt", urls)]);
+}